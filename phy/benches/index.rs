@@ -0,0 +1,90 @@
+//! Measures the time taken by `BodyIndex::update` per tick.
+//!
+//! Run with `cargo bench -p eviov-phy --bench index`.
+
+use std::time::Instant;
+
+use nalgebra::Vector2;
+use specs::{Builder, World, WorldExt};
+
+use phy::collision::BoundingBox;
+use phy::{Body, BodyIndex, Orbit, OrbitalState, OrbitingBody};
+
+/// The number of ticks measured for each population.
+const TICKS: u32 = 200;
+
+/// A deterministic xorshift generator of values in `[0, 1)`.
+fn random(seed: &mut u64) -> f64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    (*seed >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Indexes `count` bodies on random elliptic orbits and measures the average update time.
+///
+/// The speed of each body deviates from the circular speed by up to `spread` of it,
+/// and its heading deviates from the tangent by up to `spread` radians,
+/// so a larger `spread` makes more orbits cross each other.
+fn bench(count: usize, spread: f64) {
+    let m = units::Mass(1e6);
+    let t0 = units::GameInstant(0);
+    let mut world = World::new();
+    world.register::<Body>();
+    world.register::<BoundingBox>();
+    let star = world.create_entity().build();
+
+    let mut seed = 0x2545_f491_4f6c_dd1d;
+    let entities: Vec<_> = (0..count)
+        .map(|_| {
+            let radius = 1000. + random(&mut seed) * 9000.;
+            let bearing = random(&mut seed) * std::f64::consts::PI * 2.;
+            let speed = (m.0 / radius).sqrt() * (1. + (random(&mut seed) * 2. - 1.) * spread);
+            let heading =
+                bearing + std::f64::consts::FRAC_PI_2 + (random(&mut seed) * 2. - 1.) * spread;
+            let state = OrbitalState::new(
+                units::Position(Vector2::new(radius * bearing.cos(), radius * bearing.sin())),
+                units::Velocity::of(units::Displace(Vector2::new(
+                    speed * heading.cos(),
+                    speed * heading.sin(),
+                ))),
+            );
+            let orbit = Orbit::from_states(state, t0, m);
+            world
+                .create_entity()
+                .with(Body::Orbiting(OrbitingBody::new(orbit, star)))
+                .build()
+        })
+        .collect();
+
+    let store_body = world.read_storage::<Body>();
+    let store_bb = world.read_storage::<BoundingBox>();
+    let extents = BodyIndex::extents(&store_bb);
+    let mut index = BodyIndex::new();
+    for &entity in &entities {
+        index.insert(entity, &store_body, &extents, t0);
+    }
+    index.update(&store_body, t0);
+
+    let start = Instant::now();
+    for tick in 1..=TICKS {
+        index.update(&store_body, units::GameInstant(tick));
+    }
+    let elapsed = start.elapsed();
+    #[cfg(debug_assertions)]
+    index.check_invariants(&store_body, units::GameInstant(TICKS));
+    println!(
+        "{} orbiting bodies, spread {}: {:?} per tick",
+        count,
+        spread,
+        elapsed / TICKS
+    );
+}
+
+fn main() {
+    for &spread in &[0.001, 0.2] {
+        for &count in &[1000, 10000] {
+            bench(count, spread);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Closest approach between two orbits.
+
+use getset::*;
+
+use super::search::{self, SAMPLES_PER_SCALE};
+use super::Orbit;
+
+/// A local minimum of the distance between two orbits.
+#[derive(Debug, Clone, Copy, Getters, CopyGetters)]
+pub struct Approach {
+    /// The tick closest to the moment of minimum distance.
+    #[getset(get_copy = "pub")]
+    instant: units::GameInstant,
+    /// The minimum distance, which may be attained between two ticks.
+    #[getset(get_copy = "pub")]
+    distance: units::Length,
+}
+
+impl Orbit {
+    /// Finds the moments of locally minimum distance between this orbit and `other`
+    /// from `from` to `until`, in chronological order.
+    ///
+    /// Both orbits must be in the same star system.
+    /// Minima at the boundaries of the time window are not reported.
+    ///
+    /// The orbits are sampled at a fraction of the time taken to pass the faster periapsis,
+    /// so minima closer together than that interval may be merged.
+    /// Very long windows are sampled more sparsely to bound the computation.
+    pub fn closest_approaches(
+        &self,
+        other: &Self,
+        from: units::GameInstant,
+        until: units::GameInstant,
+    ) -> Vec<Approach> {
+        let from = f64::from(from.0);
+        let until = f64::from(until.0);
+        let step = self.time_scale().min(other.time_scale()) / SAMPLES_PER_SCALE;
+        let distance_sq = |time: f64| {
+            (self.position_at(time, 0.) - other.position_at(time, 0.))
+                .0
+                .norm_squared()
+        };
+
+        let samples: Vec<(f64, f64)> = search::sample_times(from, until, step)
+            .map(|time| (time, distance_sq(time)))
+            .collect();
+
+        samples
+            .windows(3)
+            .filter(|window| window[0].1 > window[1].1 && window[1].1 <= window[2].1)
+            .map(|window| {
+                let time = search::minimize(distance_sq, window[0].0, window[2].0);
+                Approach {
+                    instant: units::GameInstant(time.round().max(from).min(until) as u32),
+                    distance: distance_sq(time).sqrt(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::{OrbitalState, Rotation};
+
+    /// Creates a circular orbit of radius `radius` at bearing `bearing` at instant `t`.
+    fn circular(
+        radius: f64,
+        bearing: f64,
+        rotation: Rotation,
+        t: units::GameInstant,
+        m: units::Mass,
+    ) -> Orbit {
+        let speed = (m.0 / radius).sqrt() * rotation.signum();
+        let (sin, cos) = bearing.sin_cos();
+        let state = OrbitalState::new(
+            units::Position(Vector2::new(radius * cos, radius * sin)),
+            units::Velocity::of(units::Displace(Vector2::new(-speed * sin, speed * cos))),
+        );
+        Orbit::from_states(state, t, m)
+    }
+
+    #[test]
+    fn circular_synodic_period() {
+        let m = units::Mass(1000.);
+        let (from, until) = (units::GameInstant(100), units::GameInstant(5000));
+        let (inner, outer) = (100., 150.);
+        let phase = 1.;
+
+        for &rotation in &[Rotation::Counterclockwise, Rotation::Clockwise] {
+            let a = circular(inner, 0., rotation, from, m);
+            let b = circular(outer, phase * rotation.signum(), rotation, from, m);
+            let approaches = a.closest_approaches(&b, from, until);
+
+            // the inner orbit gains one revolution on the outer orbit every synodic period
+            let relative = (m.0 / inner.powi(3)).sqrt() - (m.0 / outer.powi(3)).sqrt();
+            let synodic = PI * 2. / relative;
+            let expected: Vec<f64> = (0..)
+                .map(|k| f64::from(from.0) + (phase + PI * 2. * f64::from(k)) / relative)
+                .take_while(|&time| time < f64::from(until.0))
+                .collect();
+            assert!(expected.len() >= 3, "synodic period {}", synodic);
+
+            // dense brute force sampling of the distance
+            let distance = |time: f64| (a.position_at(time, 0.) - b.position_at(time, 0.)).0.norm();
+            let step = 0.01;
+            let samples: Vec<(f64, f64)> = (0..)
+                .map(|i| f64::from(from.0) + f64::from(i) * step)
+                .take_while(|&time| time <= f64::from(until.0))
+                .map(|time| (time, distance(time)))
+                .collect();
+            let brute: Vec<(f64, f64)> = samples
+                .windows(3)
+                .filter(|window| window[0].1 > window[1].1 && window[1].1 <= window[2].1)
+                .map(|window| window[1])
+                .collect();
+
+            assert_eq!(approaches.len(), expected.len(), "{:?}", approaches);
+            assert_eq!(brute.len(), expected.len(), "{:?}", brute);
+            for ((approach, &time), &(brute_time, brute_distance)) in
+                approaches.iter().zip(&expected).zip(&brute)
+            {
+                assert!((f64::from(approach.instant().0) - time).abs() <= 0.5);
+                assert!((brute_time - time).abs() <= step);
+                assert!((approach.distance() - (outer - inner)).abs() < 1e-6);
+                assert!(approach.distance() <= brute_distance + 1e-9);
+            }
+        }
+    }
+}
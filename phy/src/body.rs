@@ -1,6 +1,6 @@
 use getset::*;
 
-use super::{BodyIndex, Orbit};
+use super::{BodyIndex, Orbit, OrbitalState};
 
 /// A gravity-dependent object in a solar system.
 #[derive(Debug)]
@@ -62,6 +62,84 @@ pub struct StandingBody {
     parent: specs::Entity,
 }
 
+impl OrbitingBody {
+    /// Constructs an orbiting body.
+    pub fn new(orbit: Orbit, parent: specs::Entity) -> Self {
+        Self { orbit, parent }
+    }
+
+    /// Converts this body to an accelerating body at time `t`.
+    ///
+    /// `m` is the strength of the parent star.
+    pub fn to_accelerating(&self, t: units::GameInstant, m: units::Mass) -> AccelBody {
+        AccelBody::from_state(self.orbit.to_state(t, m), self.parent)
+    }
+
+    /// Applies an instantaneous velocity change at time `t`.
+    ///
+    /// `m` is the strength of the parent star.
+    pub fn apply_impulse(
+        &mut self,
+        t: units::GameInstant,
+        delta_v: units::Velocity,
+        m: units::Mass,
+    ) {
+        self.orbit = self.orbit.apply_impulse(t, delta_v, m);
+    }
+}
+
+impl StandingBody {
+    /// Constructs a standing body.
+    pub fn new(position: units::Position, parent: specs::Entity) -> Self {
+        Self { position, parent }
+    }
+}
+
+impl AccelBody {
+    /// Constructs an accelerating body.
+    pub fn new(
+        position: units::Position,
+        velocity: units::Velocity,
+        parent: specs::Entity,
+    ) -> Self {
+        Self {
+            position,
+            velocity,
+            parent,
+        }
+    }
+
+    /// Constructs an accelerating body from its orbital state.
+    pub fn from_state(state: OrbitalState, parent: specs::Entity) -> Self {
+        Self::new(state.position(), state.velocity(), parent)
+    }
+
+    /// Returns the current position and velocity as an `OrbitalState`.
+    pub fn state(&self) -> OrbitalState {
+        OrbitalState::new(self.position, self.velocity)
+    }
+
+    /// Moves this body from time `t` to its state one tick later,
+    /// following the orbit through its current state.
+    ///
+    /// `m` is the strength of the parent star.
+    /// Other accelerations should be applied to the velocity before each step.
+    pub fn advance(&mut self, t: units::GameInstant, m: units::Mass) {
+        let orbit = Orbit::from_states(self.state(), t, m);
+        let state = orbit.to_state(t + units::GameDuration::UNIT, m);
+        self.position = state.position();
+        self.velocity = state.velocity();
+    }
+
+    /// Converts this body to an orbiting body at time `t`,
+    /// assuming no forces other than gravitation apply from then on.
+    ///
+    /// `m` is the strength of the parent star.
+    pub fn to_orbiting(&self, t: units::GameInstant, m: units::Mass) -> OrbitingBody {
+        OrbitingBody::new(Orbit::from_states(self.state(), t, m), self.parent)
+    }
+}
+
 impl Body {
     /// Retrieves the parent of this body.
     ///
@@ -75,6 +153,90 @@ impl Body {
         }
     }
 
+    /// Applies an instantaneous velocity change at time `t`.
+    ///
+    /// `m` is the strength of the parent star.
+    /// A standing body is lifted off its star and becomes an orbiting body.
+    ///
+    /// Panics if the body is a root star.
+    pub fn apply_impulse(
+        &mut self,
+        t: units::GameInstant,
+        delta_v: units::Velocity,
+        m: units::Mass,
+    ) {
+        match self {
+            Self::Root(_) => unreachable!("A root star cannot move"),
+            Self::Orbiting(body) => body.apply_impulse(t, delta_v, m),
+            Self::Accelerating(body) => {
+                let _ = body.set_velocity(body.velocity() + delta_v);
+            }
+            Self::Standing(body) => {
+                let state = OrbitalState::new(body.position(), delta_v);
+                let orbit = Orbit::from_states(state, t, m);
+                *self = Self::Orbiting(OrbitingBody::new(orbit, body.parent()))
+            }
+        }
+    }
+
+    /// Moves this body by `delta` at time `t` without changing its velocity.
+    ///
+    /// `m` is the strength of the parent star.
+    /// An orbiting body follows a new orbit from the moved position.
+    ///
+    /// Panics if the body is a root star.
+    pub fn displace(&mut self, t: units::GameInstant, delta: units::Displace, m: units::Mass) {
+        match self {
+            Self::Root(_) => unreachable!("A root star cannot move"),
+            Self::Orbiting(body) => {
+                let state = body.orbit().to_state(t, m);
+                let state = OrbitalState::new(state.position() + delta, state.velocity());
+                let _ = body.set_orbit(Orbit::from_states(state, t, m));
+            }
+            Self::Accelerating(body) => {
+                let _ = body.set_position(body.position() + delta);
+            }
+            Self::Standing(body) => {
+                let _ = body.set_position(body.position() + delta);
+            }
+        }
+    }
+
+    /// Computes the position and velocity of this body at time `t`.
+    ///
+    /// `m` is the strength of the parent star.
+    ///
+    /// Panics if the body is a root star.
+    pub fn state(&self, t: units::GameInstant, m: units::Mass) -> OrbitalState {
+        OrbitalState::new(self.position(t), self.velocity(t, m))
+    }
+
+    /// Moves this body into the star system of `parent`,
+    /// where its position and velocity at time `t` are given by `state`.
+    ///
+    /// `m` is the strength of the new parent star.
+    /// An orbiting body follows a new orbit, and an accelerating body keeps accelerating.
+    ///
+    /// Panics if the body is a root star or a standing body.
+    pub fn reparent(
+        &mut self,
+        state: OrbitalState,
+        t: units::GameInstant,
+        parent: specs::Entity,
+        m: units::Mass,
+    ) {
+        match self {
+            Self::Root(_) => unreachable!("A root star cannot change parent"),
+            Self::Orbiting(_) => {
+                *self = Self::Orbiting(OrbitingBody::new(Orbit::from_states(state, t, m), parent))
+            }
+            Self::Accelerating(_) => {
+                *self = Self::Accelerating(AccelBody::from_state(state, parent))
+            }
+            Self::Standing(_) => unreachable!("A standing body cannot leave its star"),
+        }
+    }
+
     /// Computes the position of the entity.
     ///
     /// Panics if the body is a root star.
@@ -104,6 +266,52 @@ impl specs::Component for Body {
     type Storage = specs::storage::VecStorage<Self>;
 }
 
+/// The inertial mass of a body.
+///
+/// This is only used to exchange momentum in collisions,
+/// and is unrelated to the gravitational strength of a `Star`.
+/// Bodies without this component are immovable in collisions.
+#[derive(Debug, Clone, Copy, Getters, Setters, CopyGetters)]
+pub struct Inertia {
+    /// The mass of the body.
+    #[getset(get_copy = "pub", set = "pub")]
+    mass: units::Mass,
+}
+
+impl Inertia {
+    /// Constructs the inertia of a body with the given mass.
+    pub fn new(mass: units::Mass) -> Self {
+        Self { mass }
+    }
+}
+
+impl specs::Component for Inertia {
+    type Storage = specs::storage::VecStorage<Self>;
+}
+
+/// The orientation of a body.
+///
+/// The shapes of the bounding boxes of the body rotate counterclockwise by this bearing.
+/// The offsets of the bounding boxes are rotated along with the body.
+/// Bodies without this component have the zero attitude.
+#[derive(Debug, Clone, Copy, Getters, Setters, CopyGetters)]
+pub struct Attitude {
+    /// The bearing of the local X axis of the body.
+    #[getset(get_copy = "pub", set = "pub")]
+    bearing: units::Bearing,
+}
+
+impl Attitude {
+    /// Constructs the attitude of a body facing `bearing`.
+    pub fn new(bearing: units::Bearing) -> Self {
+        Self { bearing }
+    }
+}
+
+impl specs::Component for Attitude {
+    type Storage = specs::storage::VecStorage<Self>;
+}
+
 /// An object with a non-negligible gravitational field.
 ///
 /// An entity with `Star` also has a `Body` component iff it is not a root star.
@@ -118,6 +326,16 @@ pub struct Star {
     #[getset(get_copy = "pub", set = "pub")]
     field_radius: units::Length,
 
+    /// The length of one unit of this star system, measured in units of the parent star system.
+    ///
+    /// Positions and velocities are multiplied by this value
+    /// when they are re-expressed in the parent star system.
+    /// This value is unused for root stars.
+    ///
+    /// Defaults to 1, i.e. the same length unit as the parent star system.
+    #[getset(get_copy = "pub", set = "pub")]
+    scale: f64,
+
     /// Mass of the star, used for orbit calculation.
     #[getset(get_copy = "pub", set = "pub")]
     strength: units::Mass,
@@ -127,6 +345,18 @@ pub struct Star {
     index: BodyIndex,
 }
 
+impl Star {
+    /// Constructs a star with an empty index and a `scale` of 1.
+    pub fn new(field_radius: units::Length, strength: units::Mass) -> Self {
+        Self {
+            field_radius,
+            scale: 1.,
+            strength,
+            index: BodyIndex::new(),
+        }
+    }
+}
+
 impl specs::Component for Star {
     type Storage = specs::storage::BTreeStorage<Self>;
 }
@@ -1,23 +1,121 @@
 //! Bounding boxes
 
+use getset::*;
+use nalgebra::Vector2;
+
 /// One of the bounding boxes of a body.
 ///
 /// A body may have multiple BBs,
 /// e.g. atmosphere and land.
 /// Each such BB is represented by an entity
 /// with a `BoundingBox` component.
-#[derive(Debug)]
+#[derive(Debug, Clone, Getters, CopyGetters)]
 pub struct BoundingBox {
     /// The shape of the BB.
+    #[getset(get = "pub")]
     shape: Shape,
     /// The behavioural variant of the BB.
+    #[getset(get = "pub")]
     variant: Variant,
     /// The parent entity of the BB.
     ///
     /// The parent must have a `Body` component.
+    #[getset(get_copy = "pub")]
     parent: specs::Entity,
-    /// The position of the BB relative to the parent.
+    /// The position of the BB relative to the parent at zero attitude.
+    ///
+    /// Use `placed_offset` to get the offset of the BB for a rotated parent.
+    #[getset(get_copy = "pub")]
     offset: units::Displace,
+    /// The collision layers this BB belongs to.
+    #[getset(get_copy = "pub", set = "pub")]
+    layers: Layers,
+    /// The collision layers this BB collides with.
+    ///
+    /// Two BBs only collide if each of them belongs to a layer in the mask of the other.
+    #[getset(get_copy = "pub", set = "pub")]
+    mask: Layers,
+}
+
+impl BoundingBox {
+    /// Constructs a bounding box.
+    pub fn new(
+        shape: Shape,
+        variant: Variant,
+        parent: specs::Entity,
+        offset: units::Displace,
+    ) -> Self {
+        Self {
+            shape,
+            variant,
+            parent,
+            offset,
+            layers: Layers::ALL,
+            mask: Layers::ALL,
+        }
+    }
+
+    /// Sets the collision layers and mask of the BB.
+    ///
+    /// By default, a BB belongs to and collides with all layers.
+    pub fn with_layers(mut self, layers: Layers, mask: Layers) -> Self {
+        self.layers = layers;
+        self.mask = mask;
+        self
+    }
+
+    /// Computes the position of the BB relative to the parent
+    /// when the parent has the attitude `attitude`.
+    pub fn placed_offset(&self, attitude: units::Bearing) -> units::Displace {
+        let (sin, cos) = attitude.0.sin_cos();
+        let offset = self.offset.0;
+        units::Displace(Vector2::new(
+            offset[0] * cos - offset[1] * sin,
+            offset[0] * sin + offset[1] * cos,
+        ))
+    }
+
+    /// Checks whether the collision layers and masks of two BBs allow them to collide.
+    pub fn collides_with(&self, other: &Self) -> bool {
+        self.mask.intersects(other.layers) && other.mask.intersects(self.layers)
+    }
+}
+
+/// A set of collision layers, e.g. projectiles, debris or sensors.
+///
+/// Up to 32 layers are supported, each represented by one bit.
+/// The meaning of each layer is defined by the game rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Layers(pub u32);
+
+impl Layers {
+    /// The set of all layers.
+    pub const ALL: Layers = Layers(u32::MAX);
+
+    /// The empty set of layers.
+    ///
+    /// A BB in no layers, or with an empty mask, never collides.
+    pub const NONE: Layers = Layers(0);
+
+    /// Constructs the set containing only the layer numbered `layer`,
+    /// which must be less than 32.
+    pub fn single(layer: u32) -> Self {
+        assert!(layer < 32, "Collision layer {} out of range", layer);
+        Layers(1 << layer)
+    }
+
+    /// Checks whether the two sets have any layer in common.
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl std::ops::BitOr for Layers {
+    type Output = Layers;
+
+    fn bitor(self, other: Self) -> Self {
+        Layers(self.0 | other.0)
+    }
 }
 
 /// The shape of a bounding box (BB).
@@ -25,7 +123,7 @@ pub struct BoundingBox {
 /// BB shape affects the following:
 /// - collision between BBs
 /// - drag effect of fluids on the body of this BB
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Shape {
     /// A point BB.
     ///
@@ -40,27 +138,624 @@ pub enum Shape {
         /// Radius of the circle
         radius: units::Length,
     },
+
+    /// A convex polygon BB.
+    ///
+    /// Collides with another BB when their interiors overlap.
+    Polygon {
+        /// The vertices of the polygon in order around its boundary,
+        /// relative to the position of the BB at zero attitude.
+        ///
+        /// The polygon must be convex; use `Shape::polygon` to validate it.
+        vertices: Vec<units::Displace>,
+    },
+
+    /// A capsule BB, i.e. the points within `radius` of a line segment.
+    ///
+    /// The segment is centred at the position of the BB,
+    /// and is parallel to the X axis at zero attitude.
+    Capsule {
+        /// Half of the length of the segment
+        half_length: units::Length,
+        /// Radius around the segment
+        radius: units::Length,
+    },
+
+    /// An annulus BB, i.e. the points between two concentric circles,
+    /// such as a planetary ring or an atmospheric layer.
+    ///
+    /// Collides with another BB when their interiors overlap.
+    /// A BB entirely within the inner circle does not collide with the annulus.
+    Annulus {
+        /// Radius of the inner circle
+        inner: units::Length,
+        /// Radius of the outer circle
+        outer: units::Length,
+    },
+}
+
+/// A shape placed in a star system,
+/// expressed as the points within `radius` of the convex hull of `vertices`.
+///
+/// The hull is a point, a segment or a polygon with one, two or more vertices.
+struct Core {
+    vertices: Vec<Vector2<f64>>,
+    radius: units::Length,
 }
 
 impl Shape {
-    /// Computes the inscribing circular BB for quick collision filtering.
+    /// Constructs a convex polygon BB from its vertices
+    /// in either order around its boundary.
+    ///
+    /// Panics if the polygon is not convex or has fewer than three vertices.
+    pub fn polygon(vertices: Vec<units::Displace>) -> Self {
+        assert!(is_convex(&vertices), "Polygon BB is not convex");
+        Self::Polygon { vertices }
+    }
+
+    /// Computes the enclosing circular BB for quick collision filtering.
     pub fn radius(&self) -> units::Length {
         match self {
             Self::Point => units::Length::default(),
             Self::Circle { radius } => *radius,
+            Self::Polygon { vertices } => vertices
+                .iter()
+                .map(|vertex| vertex.0.norm())
+                .fold(0., f64::max),
+            Self::Capsule {
+                half_length,
+                radius,
+            } => half_length + radius,
+            Self::Annulus { outer, .. } => *outer,
+        }
+    }
+
+    /// Tests whether this shape at `position` and `attitude`
+    /// intersects `other` at `other_position` and `other_attitude`.
+    ///
+    /// Returns a point inside both shapes if they intersect,
+    /// together with the contact normal,
+    /// i.e. the unit vector from this shape towards `other`
+    /// along which the shapes are pushed apart.
+    /// A shape overlapping the inner edge of an annulus more shallowly than its outer edge
+    /// is pushed towards the centre of the annulus.
+    pub fn contact(
+        &self,
+        position: units::Position,
+        attitude: units::Bearing,
+        other: &Self,
+        other_position: units::Position,
+        other_attitude: units::Bearing,
+    ) -> Option<(units::Position, units::Displace)> {
+        let contact = match (self, other) {
+            (
+                Self::Annulus { inner, outer },
+                Self::Annulus {
+                    inner: other_inner,
+                    outer: other_outer,
+                },
+            ) => annuli_contact(
+                (position.0, *inner, *outer),
+                (other_position.0, *other_inner, *other_outer),
+            ),
+            (Self::Annulus { inner, outer }, _) => other
+                .core(other_position, other_attitude)
+                .annulus_contact(position.0, *inner, *outer)
+                .map(|(point, normal)| (point, -normal)),
+            (_, Self::Annulus { inner, outer }) => {
+                self.core(position, attitude)
+                    .annulus_contact(other_position.0, *inner, *outer)
+            }
+            _ => self
+                .core(position, attitude)
+                .contact(&other.core(other_position, other_attitude)),
+        };
+        contact.map(|(point, normal)| (units::Position(point), units::Displace(normal)))
+    }
+
+    /// Computes the signed distance between this shape at `position` and `attitude`
+    /// and `other` at `other_position` and `other_attitude`.
+    ///
+    /// The result is the distance between the shapes if they are apart,
+    /// and is negative iff the shapes intersect.
+    /// Translating either shape changes the result by no more than the translated distance.
+    pub fn separation(
+        &self,
+        position: units::Position,
+        attitude: units::Bearing,
+        other: &Self,
+        other_position: units::Position,
+        other_attitude: units::Bearing,
+    ) -> units::Length {
+        match (self, other) {
+            (
+                Self::Annulus { inner, outer },
+                Self::Annulus {
+                    inner: other_inner,
+                    outer: other_outer,
+                },
+            ) => {
+                let distance = (other_position - position).0.norm();
+                (distance - outer - other_outer)
+                    .max(other_inner - distance - outer)
+                    .max(inner - distance - other_outer)
+            }
+            (Self::Annulus { inner, outer }, _) => other
+                .core(other_position, other_attitude)
+                .annulus_separation(position.0, *inner, *outer),
+            (_, Self::Annulus { inner, outer }) => self
+                .core(position, attitude)
+                .annulus_separation(other_position.0, *inner, *outer),
+            _ => {
+                let core = self.core(position, attitude);
+                let other_core = other.core(other_position, other_attitude);
+                let (distance, _, _) = core.distance(&other_core);
+                core.separation(&other_core, distance)
+            }
         }
     }
 
-    /// Computes the drag effect of this shape against the given direction
-    pub fn drag(&self, direction: impl units::Direction) -> f32 {
-        todo!()
+    /// Computes the drag area of this shape moving in the given direction,
+    /// which is relative to the attitude of the shape.
+    ///
+    /// In two dimensions, this is the width of the shape perpendicular to `direction`.
+    /// A point has no drag area.
+    pub fn drag(&self, direction: impl units::Direction) -> units::Length {
+        if let Self::Annulus { outer, .. } = self {
+            return outer * 2.;
+        }
+        let direction = direction.to_unit_vector().0;
+        let axis = Vector2::new(-direction[1], direction[0]);
+        let core = self.core(units::Position::origin(), units::Bearing(0.));
+        let (low, high) = core.project(axis);
+        high - low + core.radius * 2.
+    }
+
+    /// Places this shape at `position` and `attitude`.
+    ///
+    /// Panics if the shape is an annulus, which is not convex.
+    fn core(&self, position: units::Position, attitude: units::Bearing) -> Core {
+        let (sin, cos) = attitude.0.sin_cos();
+        let place = |offset: Vector2<f64>| {
+            position.0
+                + Vector2::new(
+                    offset[0] * cos - offset[1] * sin,
+                    offset[0] * sin + offset[1] * cos,
+                )
+        };
+        let (vertices, radius) = match self {
+            Self::Point => (vec![position.0], 0.),
+            Self::Circle { radius } => (vec![position.0], *radius),
+            Self::Polygon { vertices } => {
+                debug_assert!(is_convex(vertices), "Polygon BB is not convex");
+                (vertices.iter().map(|vertex| place(vertex.0)).collect(), 0.)
+            }
+            Self::Capsule {
+                half_length,
+                radius,
+            } => (
+                vec![
+                    place(Vector2::new(-half_length, 0.)),
+                    place(Vector2::new(*half_length, 0.)),
+                ],
+                *radius,
+            ),
+            Self::Annulus { .. } => unreachable!("An annulus has no convex core"),
+        };
+        Core { vertices, radius }
     }
 }
 
+impl Core {
+    /// Finds a point in both this shape and `other` if they intersect,
+    /// together with the contact normal from this shape towards `other`.
+    ///
+    /// The normal is along the closest points of the hulls if they are apart,
+    /// otherwise along the axis of least penetration.
+    fn contact(&self, other: &Self) -> Option<(Vector2<f64>, Vector2<f64>)> {
+        let (distance, closest, other_closest) = self.distance(other);
+        if self.separation(other, distance) >= 0. {
+            return None;
+        }
+
+        let contact = if distance > 0. {
+            // the middle of the overlap along the line between the closest points
+            let low = (-self.radius).max(distance - other.radius);
+            let high = self.radius.min(distance + other.radius);
+            let normal = (other_closest - closest) / distance;
+            (closest + normal * ((low + high) / 2.), normal)
+        } else {
+            let normal = self
+                .penetration(other)
+                .map_or(Vector2::new(1., 0.), |(_, axis)| axis);
+            (self.common_point(other).unwrap_or(closest), normal)
+        };
+        Some(contact)
+    }
+
+    /// Computes the signed distance to the annulus at `centre`.
+    ///
+    /// The shape and the annulus intersect iff the shape overlaps the outer circle
+    /// and is not contained by the inner circle.
+    fn annulus_separation(&self, centre: Vector2<f64>, inner: f64, outer: f64) -> f64 {
+        let disk = Self::disk(centre, outer);
+        let (distance, _, _) = self.distance(&disk);
+        let (_, farthest) = self.farthest(centre);
+        self.separation(&disk, distance).max(inner - farthest)
+    }
+
+    /// Finds a point in both this shape and the annulus at `centre` if they intersect,
+    /// together with the contact normal from this shape towards the annulus.
+    ///
+    /// The normal is outwards from the centre if the shape overlaps the annulus
+    /// more shallowly from the outer edge than from the inner edge, and inwards otherwise.
+    fn annulus_contact(
+        &self,
+        centre: Vector2<f64>,
+        inner: f64,
+        outer: f64,
+    ) -> Option<(Vector2<f64>, Vector2<f64>)> {
+        if self.annulus_separation(centre, inner, outer) >= 0. {
+            return None;
+        }
+        let disk = Self::disk(centre, outer);
+        let (near, outer_normal) = self.contact(&disk)?;
+        let (far, far_distance) = self.farthest(centre);
+        let outer_overlap = -self.separation(&disk, self.distance(&disk).0);
+        let normal = if far_distance - inner >= outer_overlap {
+            outer_normal
+        } else if far_distance > 0. {
+            // the shape is pushed back into the inner circle
+            (far - centre) / far_distance
+        } else {
+            Vector2::new(1., 0.)
+        };
+        if (near - centre).norm() > inner {
+            return Some((near, normal));
+        }
+
+        // move from inside the inner circle towards the farthest point of the shape,
+        // which are both in this convex shape, until the middle of the annulus
+        let target = (inner + outer.min(far_distance)) / 2.;
+        let (start, step) = (near - centre, far - near);
+        let a = step.norm_squared();
+        let b = start.dot(&step) * 2.;
+        let c = start.norm_squared() - target * target;
+        let ratio = (-b + (b * b - a * c * 4.).max(0.).sqrt()) / (a * 2.);
+        Some((near + step * ratio.clamp(0., 1.), normal))
+    }
+
+    /// Constructs the core of a circle.
+    fn disk(centre: Vector2<f64>, radius: f64) -> Self {
+        Self {
+            vertices: vec![centre],
+            radius,
+        }
+    }
+
+    /// Finds the point of this shape farthest from `point`, together with its distance.
+    fn farthest(&self, point: Vector2<f64>) -> (Vector2<f64>, f64) {
+        let vertex = self
+            .vertices
+            .iter()
+            .copied()
+            .max_by(|a, b| {
+                (a - point)
+                    .norm()
+                    .partial_cmp(&(b - point).norm())
+                    .expect("Vertex distance is NaN")
+            })
+            .expect("A core has at least one vertex");
+        let offset = vertex - point;
+        let distance = offset.norm();
+        let direction = if distance > 0. {
+            offset / distance
+        } else {
+            Vector2::new(1., 0.)
+        };
+        (vertex + direction * self.radius, distance + self.radius)
+    }
+
+    /// Returns the edges of the hull.
+    ///
+    /// A point has a single degenerate edge, and a segment has a single edge.
+    fn edges(&self) -> impl Iterator<Item = (Vector2<f64>, Vector2<f64>)> + '_ {
+        let n = self.vertices.len();
+        let count = if n <= 2 { 1 } else { n };
+        (0..count).map(move |i| (self.vertices[i], self.vertices[(i + 1) % n]))
+    }
+
+    /// Computes the orientation of the polygon hull,
+    /// i.e. the sign of its signed area, or `None` if the hull has no area.
+    fn orientation(&self) -> Option<f64> {
+        if self.vertices.len() < 3 {
+            return None;
+        }
+        let area: f64 = self.edges().map(|(from, to)| util::cross2d(from, to)).sum();
+        if area == 0. {
+            None
+        } else {
+            Some(area.signum())
+        }
+    }
+
+    /// Tests whether `point` is inside or on the boundary of the polygon hull.
+    fn contains(&self, point: Vector2<f64>) -> bool {
+        match self.orientation() {
+            Some(sign) => self
+                .edges()
+                .all(|(from, to)| util::cross2d(to - from, point - from) * sign >= 0.),
+            None => false,
+        }
+    }
+
+    /// Projects the hull onto `axis`.
+    fn project(&self, axis: Vector2<f64>) -> (f64, f64) {
+        self.vertices
+            .iter()
+            .map(|vertex| vertex.dot(&axis))
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), value| {
+                (low.min(value), high.max(value))
+            })
+    }
+
+    /// Computes the distance between the hulls,
+    /// together with the closest points on this hull and on `other`.
+    ///
+    /// The distance is zero if the hulls intersect.
+    fn distance(&self, other: &Self) -> (f64, Vector2<f64>, Vector2<f64>) {
+        if self.contains(other.vertices[0]) {
+            return (0., other.vertices[0], other.vertices[0]);
+        }
+        if other.contains(self.vertices[0]) {
+            return (0., self.vertices[0], self.vertices[0]);
+        }
+        let mut closest = (f64::INFINITY, self.vertices[0], other.vertices[0]);
+        for (from, to) in self.edges() {
+            for (other_from, other_to) in other.edges() {
+                let candidate = segment_distance((from, to), (other_from, other_to));
+                if candidate.0 < closest.0 {
+                    closest = candidate;
+                }
+            }
+        }
+        closest
+    }
+
+    /// Computes the minimum overlap of the projections of intersecting hulls
+    /// over the normals of their edges,
+    /// together with the axis of the minimum overlap, directed from this hull towards `other`.
+    ///
+    /// The overlap is positive iff the interiors of the hulls overlap,
+    /// treating a point or segment as its own interior.
+    /// Returns `None` for two points, which have no overlap.
+    fn penetration(&self, other: &Self) -> Option<(f64, Vector2<f64>)> {
+        self.edges()
+            .chain(other.edges())
+            .filter(|(from, to)| from != to)
+            .map(|(from, to)| {
+                let edge = (to - from).normalize();
+                let axis = Vector2::new(-edge[1], edge[0]);
+                let (low, high) = self.project(axis);
+                let (other_low, other_high) = other.project(axis);
+                // the distance to move `other` along the axis or against it to separate the hulls
+                let (forward, backward) = (high - other_low, other_high - low);
+                if forward <= backward {
+                    (forward, axis)
+                } else {
+                    (backward, -axis)
+                }
+            })
+            .min_by(|a, b| a.0.partial_cmp(&b.0).expect("Overlap is NaN"))
+    }
+
+    /// Computes the signed distance to `other`,
+    /// given the `distance` between the hulls.
+    fn separation(&self, other: &Self, distance: f64) -> f64 {
+        let radius = self.radius + other.radius;
+        if distance > 0. {
+            distance - radius
+        } else {
+            let overlap = self.penetration(other).map_or(0., |(overlap, _)| overlap);
+            -(overlap + radius)
+        }
+    }
+
+    /// Finds a point in both hulls, which are known to intersect.
+    ///
+    /// If either hull is a polygon, the point is in the interior of the intersection
+    /// whenever the interiors overlap.
+    /// Returns `None` if neither hull is a polygon.
+    fn common_point(&self, other: &Self) -> Option<Vector2<f64>> {
+        let clipped = if self.orientation().is_some() {
+            other.clip(self)
+        } else if other.orientation().is_some() {
+            self.clip(other)
+        } else {
+            return None;
+        };
+        if clipped.is_empty() {
+            return None;
+        }
+        let sum = clipped
+            .iter()
+            .fold(Vector2::new(0., 0.), |sum, &vertex| sum + vertex);
+        Some(sum / clipped.len() as f64)
+    }
+
+    /// Clips the hull by the polygon hull `clip`.
+    fn clip(&self, clip: &Self) -> Vec<Vector2<f64>> {
+        let sign = clip
+            .orientation()
+            .expect("Clipping by a degenerate polygon");
+        let mut output = self.vertices.clone();
+        for (from, to) in clip.edges() {
+            let input = std::mem::take(&mut output);
+            let side = |point: Vector2<f64>| util::cross2d(to - from, point - from) * sign;
+            for (i, &current) in input.iter().enumerate() {
+                let previous = input[(i + input.len() - 1) % input.len()];
+                let (current_side, previous_side) = (side(current), side(previous));
+                if (current_side >= 0.) != (previous_side >= 0.) {
+                    let ratio = previous_side / (previous_side - current_side);
+                    output.push(previous + (current - previous) * ratio);
+                }
+                if current_side >= 0. {
+                    output.push(current);
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Finds a point in two annuli, each given by its centre, inner radius and outer radius,
+/// if they intersect,
+/// together with the contact normal from the first annulus towards the second.
+///
+/// The normal is along the line of centres,
+/// towards the first annulus if either annulus overlaps the inner edge of the other
+/// more shallowly than the outer edges overlap each other.
+fn annuli_contact(
+    (centre, inner, outer): (Vector2<f64>, f64, f64),
+    (other_centre, other_inner, other_outer): (Vector2<f64>, f64, f64),
+) -> Option<(Vector2<f64>, Vector2<f64>)> {
+    let offset = other_centre - centre;
+    let distance = offset.norm();
+    let (low, high) = (inner.max(other_inner), outer.min(other_outer));
+    if distance == 0. {
+        return if low < high {
+            Some((
+                centre + Vector2::new((low + high) / 2., 0.),
+                Vector2::new(1., 0.),
+            ))
+        } else {
+            None
+        };
+    }
+
+    // Choose the distances of the point from both centres,
+    // which are in both radius ranges and satisfy the triangle inequality.
+    let ranges = Core {
+        vertices: vec![
+            Vector2::new(inner, other_inner),
+            Vector2::new(outer, other_inner),
+            Vector2::new(outer, other_outer),
+            Vector2::new(inner, other_outer),
+        ],
+        radius: 0.,
+    };
+    let sum = outer + other_outer + distance;
+    let triangle = Core {
+        vertices: vec![
+            Vector2::new(distance, 0.),
+            Vector2::new((sum + distance) / 2., (sum - distance) / 2.),
+            Vector2::new((sum - distance) / 2., (sum + distance) / 2.),
+            Vector2::new(0., distance),
+        ],
+        radius: 0.,
+    };
+    if ranges.separation(&triangle, ranges.distance(&triangle).0) >= 0. {
+        return None;
+    }
+    let radii = ranges.common_point(&triangle)?;
+
+    let along = (distance * distance + radii[0] * radii[0] - radii[1] * radii[1]) / (distance * 2.);
+    let across = (radii[0] * radii[0] - along * along).max(0.).sqrt();
+    let direction = offset / distance;
+    let point = centre + direction * along + Vector2::new(-direction[1], direction[0]) * across;
+    let outer_gap = distance - outer - other_outer;
+    let inner_gap = (other_inner - distance - outer).max(inner - distance - other_outer);
+    let normal = if outer_gap >= inner_gap {
+        direction
+    } else {
+        -direction
+    };
+    Some((point, normal))
+}
+
+/// Tests whether `vertices` are those of a convex polygon in order around its boundary.
+///
+/// Every turn along the boundary must be in the same direction,
+/// and the turns must add up to one revolution, which rules out self-intersecting polygons.
+fn is_convex(vertices: &[units::Displace]) -> bool {
+    let n = vertices.len();
+    if n < 3 {
+        return false;
+    }
+    let (mut sign, mut turning) = (0., 0.);
+    for i in 0..n {
+        let edge = vertices[(i + 1) % n].0 - vertices[i].0;
+        let next = vertices[(i + 2) % n].0 - vertices[(i + 1) % n].0;
+        let cross = util::cross2d(edge, next);
+        if cross != 0. {
+            if sign * cross < 0. {
+                return false;
+            }
+            sign = cross.signum();
+        }
+        turning += cross.atan2(edge.dot(&next));
+    }
+    (turning.abs() - std::f64::consts::PI * 2.).abs() < 1e-6
+}
+
+/// Computes the distance between two segments,
+/// together with the closest points on each segment.
+fn segment_distance(
+    (from, to): (Vector2<f64>, Vector2<f64>),
+    (other_from, other_to): (Vector2<f64>, Vector2<f64>),
+) -> (f64, Vector2<f64>, Vector2<f64>) {
+    let edge = to - from;
+    let other_edge = other_to - other_from;
+    let sides = (
+        util::cross2d(edge, other_from - from),
+        util::cross2d(edge, other_to - from),
+    );
+    let other_sides = (
+        util::cross2d(other_edge, from - other_from),
+        util::cross2d(other_edge, to - other_from),
+    );
+    if sides.0 * sides.1 < 0. && other_sides.0 * other_sides.1 < 0. {
+        // the segments cross each other
+        let point = from + edge * (other_sides.0 / (other_sides.0 - other_sides.1));
+        return (0., point, point);
+    }
+
+    let candidates = [
+        (from, closest_on_segment(from, (other_from, other_to))),
+        (to, closest_on_segment(to, (other_from, other_to))),
+        (closest_on_segment(other_from, (from, to)), other_from),
+        (closest_on_segment(other_to, (from, to)), other_to),
+    ];
+    candidates
+        .iter()
+        .map(|&(point, other_point)| ((other_point - point).norm(), point, other_point))
+        .fold((f64::INFINITY, from, other_from), |closest, candidate| {
+            if candidate.0 < closest.0 {
+                candidate
+            } else {
+                closest
+            }
+        })
+}
+
+/// Computes the point on a segment closest to `point`.
+fn closest_on_segment(
+    point: Vector2<f64>,
+    (from, to): (Vector2<f64>, Vector2<f64>),
+) -> Vector2<f64> {
+    let edge = to - from;
+    let length_squared = edge.norm_squared();
+    if length_squared == 0. {
+        return from;
+    }
+    let ratio = ((point - from).dot(&edge) / length_squared).clamp(0., 1.);
+    from + edge * ratio
+}
+
 /// The variant of a bounding box (BB).
 ///
 /// This determines how the BB can affect the colliding object.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Variant {
     /// A solid BB.
     ///
@@ -71,10 +766,612 @@ pub enum Variant {
     ///
     /// Colliding objects are constantly dragged
     /// in a direction based on the velocity of the fluid body.
-    Fluid(units::Drag),
+    /// The drag is scaled by the density of the fluid at the position of the colliding BB.
+    ///
+    /// This variant used to only carry the drag.
+    /// It now also carries the `Density` so that drag can vary with altitude,
+    /// so existing constructions need a density, e.g. `Density::Uniform` for the old behaviour.
+    Fluid(units::Drag, Density),
     // maybe magnetic field etc in the future?
 }
 
+/// The relative density of a fluid BB,
+/// as a function of the distance from the position of the BB.
+///
+/// This allows drag to vary with altitude within an atmosphere.
+#[derive(Debug, Clone)]
+pub enum Density {
+    /// The density is the same everywhere.
+    Uniform,
+
+    /// The density decays exponentially with altitude above a surface.
+    Exponential {
+        /// The distance from the position of the BB at which the relative density is 1
+        surface: units::Length,
+        /// The altitude over which the density decreases by a factor of e
+        scale_height: units::Length,
+    },
+
+    /// The fluid consists of concentric layers of uniform density.
+    ///
+    /// Each layer is given by its outer radius and its relative density,
+    /// sorted by radius in ascending order; use `Density::layers` to sort them.
+    /// The density is zero beyond the outermost layer.
+    Layers(Vec<(units::Length, f64)>),
+}
+
+impl Density {
+    /// Constructs a layered density from layers in any order,
+    /// each given by its outer radius and its relative density.
+    pub fn layers(mut layers: Vec<(units::Length, f64)>) -> Self {
+        layers.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self::Layers(layers)
+    }
+
+    /// Computes the relative density at `distance` from the position of the fluid BB.
+    pub fn at(&self, distance: units::Length) -> f64 {
+        match self {
+            Self::Uniform => 1.,
+            Self::Exponential {
+                surface,
+                scale_height,
+            } => (-(distance - surface) / scale_height).exp(),
+            Self::Layers(layers) => {
+                debug_assert!(
+                    layers.windows(2).all(|pair| pair[0].0 <= pair[1].0),
+                    "Density layers are not sorted by radius"
+                );
+                layers
+                    .iter()
+                    .find(|&&(radius, _)| distance < radius)
+                    .map_or(0., |&(_, density)| density)
+            }
+        }
+    }
+}
+
 impl specs::Component for BoundingBox {
     type Storage = specs::storage::VecStorage<Self>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(x: f64, y: f64) -> units::Position {
+        units::Position(Vector2::new(x, y))
+    }
+
+    #[test]
+    fn annulus_inner_edge_normal() {
+        let ring = Shape::Annulus {
+            inner: 10.,
+            outer: 20.,
+        };
+        let body = Shape::Circle { radius: 1. };
+        let (ring_position, body_position) = (position(0., 0.), position(0., 9.5));
+        let zero = units::Bearing(0.);
+
+        let (_, normal) = ring
+            .contact(ring_position, zero, &body, body_position, zero)
+            .expect("The body overlaps the inner edge");
+        assert!((normal.0 - Vector2::new(0., -1.)).norm() < 1e-9);
+        let (_, reverse) = body
+            .contact(body_position, zero, &ring, ring_position, zero)
+            .expect("The body overlaps the inner edge");
+        assert!((reverse.0 + normal.0).norm() < 1e-9);
+
+        // a body moving outwards approaches the ring along the normal
+        let velocity = Vector2::new(0.1, 1.);
+        assert!(velocity.dot(&normal.0) < 0.);
+
+        // pushing the body by its overlap separates it inside the inner circle
+        let overlap = -ring.separation(ring_position, zero, &body, body_position, zero);
+        assert!((overlap - 0.5).abs() < 1e-9);
+        let pushed = body_position + normal * overlap;
+        assert!(ring.separation(ring_position, zero, &body, pushed, zero) > -1e-9);
+        assert!(pushed.0.norm() + 1. <= 10. + 1e-9);
+    }
+
+    #[test]
+    fn annulus_outer_edge_normal() {
+        let ring = Shape::Annulus {
+            inner: 10.,
+            outer: 20.,
+        };
+        let body = Shape::Polygon {
+            vertices: vec![
+                units::Displace(Vector2::new(-1., -1.)),
+                units::Displace(Vector2::new(1., -1.)),
+                units::Displace(Vector2::new(1., 1.)),
+                units::Displace(Vector2::new(-1., 1.)),
+            ],
+        };
+        let zero = units::Bearing(0.);
+        let (_, normal) = ring
+            .contact(position(0., 0.), zero, &body, position(20.5, 0.), zero)
+            .expect("The body overlaps the outer edge");
+        assert!((normal.0 - Vector2::new(1., 0.)).norm() < 1e-9);
+    }
+
+    #[test]
+    fn annuli_inner_edge_normal() {
+        let ring = Shape::Annulus {
+            inner: 10.,
+            outer: 20.,
+        };
+        let small = Shape::Annulus {
+            inner: 1.,
+            outer: 2.,
+        };
+        let zero = units::Bearing(0.);
+        let (_, normal) = ring
+            .contact(position(0., 0.), zero, &small, position(-9., 0.), zero)
+            .expect("The small annulus overlaps the inner edge");
+        assert!((normal.0 - Vector2::new(1., 0.)).norm() < 1e-9);
+    }
+
+    /// The square of side 2 centred at the origin,
+    /// with its vertices counterclockwise or clockwise.
+    fn square(clockwise: bool) -> Shape {
+        let mut vertices = vec![
+            units::Displace(Vector2::new(-1., -1.)),
+            units::Displace(Vector2::new(1., -1.)),
+            units::Displace(Vector2::new(1., 1.)),
+            units::Displace(Vector2::new(-1., 1.)),
+        ];
+        if clockwise {
+            vertices.reverse();
+        }
+        Shape::polygon(vertices)
+    }
+
+    fn capsule() -> Shape {
+        Shape::Capsule {
+            half_length: 2.,
+            radius: 0.5,
+        }
+    }
+
+    fn circle() -> Shape {
+        Shape::Circle { radius: 1. }
+    }
+
+    #[test]
+    fn contact_table() {
+        use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, SQRT_2};
+
+        let zero = units::Bearing(0.);
+        let (quarter, eighth) = (units::Bearing(FRAC_PI_2), units::Bearing(FRAC_PI_4));
+        // each case places the first shape at the origin and the second at `(x, y)`,
+        // and gives the expected separation and, if they intersect, the expected normal
+        let cases = vec![
+            // polygon and point
+            (
+                square(false),
+                zero,
+                Shape::Point,
+                zero,
+                (0.5, 0.),
+                -0.5,
+                Some((1., 0.)),
+            ),
+            (
+                square(true),
+                zero,
+                Shape::Point,
+                zero,
+                (0., -0.7),
+                -0.3,
+                Some((0., -1.)),
+            ),
+            (
+                square(false),
+                eighth,
+                Shape::Point,
+                zero,
+                (1.2, 0.1),
+                -(SQRT_2 - 1.3) / SQRT_2,
+                Some((FRAC_PI_4.cos(), FRAC_PI_4.sin())),
+            ),
+            (square(false), zero, Shape::Point, zero, (3., 0.), 2., None),
+            // polygon and circle
+            (
+                square(false),
+                zero,
+                circle(),
+                zero,
+                (1.5, 0.),
+                -0.5,
+                Some((1., 0.)),
+            ),
+            (square(true), zero, circle(), zero, (0., 2.5), 0.5, None),
+            (
+                square(true),
+                eighth,
+                circle(),
+                zero,
+                (2., 0.),
+                1. - SQRT_2,
+                Some((1., 0.)),
+            ),
+            // polygon and polygon
+            (
+                square(false),
+                zero,
+                square(false),
+                zero,
+                (1.5, 0.),
+                -0.5,
+                Some((1., 0.)),
+            ),
+            (
+                square(true),
+                zero,
+                square(false),
+                zero,
+                (0., -1.8),
+                -0.2,
+                Some((0., -1.)),
+            ),
+            (
+                square(false),
+                zero,
+                square(true),
+                eighth,
+                (3., 0.),
+                2. - SQRT_2,
+                None,
+            ),
+            (
+                square(false),
+                zero,
+                square(true),
+                eighth,
+                (2.2, 0.),
+                1.2 - SQRT_2,
+                Some((1., 0.)),
+            ),
+            // polygon and capsule
+            (square(false), zero, capsule(), zero, (0., 2.), 0.5, None),
+            (
+                square(true),
+                zero,
+                capsule(),
+                zero,
+                (0., 1.3),
+                -0.2,
+                Some((0., 1.)),
+            ),
+            (
+                square(false),
+                zero,
+                capsule(),
+                quarter,
+                (1.3, 0.),
+                -0.2,
+                Some((1., 0.)),
+            ),
+            // capsule and point
+            (
+                capsule(),
+                zero,
+                Shape::Point,
+                zero,
+                (2.3, 0.),
+                -0.2,
+                Some((1., 0.)),
+            ),
+            (
+                capsule(),
+                quarter,
+                Shape::Point,
+                zero,
+                (0., 2.3),
+                -0.2,
+                Some((0., 1.)),
+            ),
+            (capsule(), zero, Shape::Point, zero, (0., 1.), 0.5, None),
+            // capsule and circle
+            (
+                capsule(),
+                quarter,
+                circle(),
+                zero,
+                (1.2, 0.5),
+                -0.3,
+                Some((1., 0.)),
+            ),
+            (
+                capsule(),
+                eighth,
+                circle(),
+                zero,
+                (-1., 1.),
+                SQRT_2 - 1.5,
+                Some((-FRAC_PI_4.cos(), FRAC_PI_4.sin())),
+            ),
+            (
+                capsule(),
+                zero,
+                circle(),
+                zero,
+                (3., 0.),
+                -0.5,
+                Some((1., 0.)),
+            ),
+            // capsule and capsule
+            (
+                capsule(),
+                zero,
+                capsule(),
+                zero,
+                (0., 0.8),
+                -0.2,
+                Some((0., 1.)),
+            ),
+            (
+                capsule(),
+                zero,
+                capsule(),
+                quarter,
+                (0., 2.8),
+                -0.2,
+                Some((0., 1.)),
+            ),
+            (capsule(), quarter, capsule(), zero, (3.2, 0.), 0.2, None),
+        ];
+
+        for (i, (shape, attitude, other, other_attitude, (x, y), expected, normal)) in
+            cases.into_iter().enumerate()
+        {
+            let (origin, other_position) = (position(0., 0.), position(x, y));
+            let separation =
+                shape.separation(origin, attitude, &other, other_position, other_attitude);
+            assert!(
+                (separation - expected).abs() < 1e-9,
+                "case {}: separation {}",
+                i,
+                separation
+            );
+            let reverse =
+                other.separation(other_position, other_attitude, &shape, origin, attitude);
+            assert!(
+                (reverse - separation).abs() < 1e-9,
+                "case {}: reverse separation {}",
+                i,
+                reverse
+            );
+
+            let contact = shape.contact(origin, attitude, &other, other_position, other_attitude);
+            let reverse = other.contact(other_position, other_attitude, &shape, origin, attitude);
+            match normal {
+                Some((nx, ny)) => {
+                    let (point, normal) =
+                        contact.unwrap_or_else(|| panic!("case {}: no contact", i));
+                    assert!(
+                        (normal.0 - Vector2::new(nx, ny)).norm() < 1e-9,
+                        "case {}: normal {}",
+                        i,
+                        normal.0
+                    );
+                    let (_, reverse) =
+                        reverse.unwrap_or_else(|| panic!("case {}: no reverse contact", i));
+                    assert!(
+                        (reverse.0 + normal.0).norm() < 1e-9,
+                        "case {}: reverse normal {}",
+                        i,
+                        reverse.0
+                    );
+
+                    // the contact point is in both shapes
+                    for &(shape, shape_position, shape_attitude) in &[
+                        (&shape, origin, attitude),
+                        (&other, other_position, other_attitude),
+                    ] {
+                        let inside = shape.separation(
+                            shape_position,
+                            shape_attitude,
+                            &Shape::Point,
+                            point,
+                            zero,
+                        );
+                        assert!(
+                            inside <= 1e-9,
+                            "case {}: contact {} outside by {}",
+                            i,
+                            point.0,
+                            inside
+                        );
+                    }
+                }
+                None => {
+                    assert!(contact.is_none(), "case {}: unexpected contact", i);
+                    assert!(reverse.is_none(), "case {}: unexpected reverse contact", i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn drag_widths() {
+        use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, SQRT_2};
+
+        let cases = vec![
+            (Shape::Point, 0., 0.),
+            (circle(), 0., 2.),
+            (circle(), 1., 2.),
+            (square(false), 0., 2.),
+            (square(true), FRAC_PI_2, 2.),
+            (square(false), FRAC_PI_4, SQRT_2 * 2.),
+            (square(true), -FRAC_PI_4 * 3., SQRT_2 * 2.),
+            (capsule(), 0., 1.),
+            (capsule(), FRAC_PI_2, 5.),
+            (capsule(), FRAC_PI_4, SQRT_2 * 2. + 1.),
+            (
+                Shape::Annulus {
+                    inner: 10.,
+                    outer: 20.,
+                },
+                1.,
+                40.,
+            ),
+        ];
+        for (shape, direction, expected) in cases {
+            let width = shape.drag(units::Bearing(direction));
+            assert!(
+                (width - expected).abs() < 1e-9,
+                "{:?} at {}: {}",
+                shape,
+                direction,
+                width
+            );
+        }
+    }
+
+    #[test]
+    fn polygon_convexity() {
+        let vertices = |points: &[(f64, f64)]| {
+            points
+                .iter()
+                .map(|&(x, y)| units::Displace(Vector2::new(x, y)))
+                .collect::<Vec<_>>()
+        };
+        assert!(is_convex(&vertices(&[(0., 0.), (1., 0.), (0., 1.)])));
+        assert!(is_convex(&vertices(&[(0., 0.), (0., 1.), (1., 0.)])));
+        // collinear vertices are allowed
+        assert!(is_convex(&vertices(&[
+            (0., 0.),
+            (1., 0.),
+            (2., 0.),
+            (0., 1.)
+        ])));
+        // a dent
+        assert!(!is_convex(&vertices(&[
+            (0., 0.),
+            (2., 0.),
+            (1., 0.5),
+            (2., 2.),
+            (0., 2.)
+        ])));
+        // a self-intersecting pentagram turns the same way at every vertex
+        let pentagram: Vec<_> = (0..5)
+            .map(|i| {
+                let angle = f64::from(i * 2) * std::f64::consts::PI * 2. / 5.;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+        assert!(!is_convex(&vertices(&pentagram)));
+        assert!(!is_convex(&vertices(&[(0., 0.), (1., 0.)])));
+    }
+
+    #[test]
+    #[should_panic(expected = "Polygon BB is not convex")]
+    fn polygon_rejects_concave() {
+        let _ = Shape::polygon(vec![
+            units::Displace(Vector2::new(0., 0.)),
+            units::Displace(Vector2::new(2., 0.)),
+            units::Displace(Vector2::new(1., 0.5)),
+            units::Displace(Vector2::new(2., 2.)),
+        ]);
+    }
+
+    #[test]
+    fn density() {
+        assert_eq!(Density::Uniform.at(0.), 1.);
+        assert_eq!(Density::Uniform.at(1e6), 1.);
+
+        let exponential = Density::Exponential {
+            surface: 100.,
+            scale_height: 10.,
+        };
+        assert!((exponential.at(100.) - 1.).abs() < 1e-12);
+        assert!((exponential.at(110.) - (-1f64).exp()).abs() < 1e-12);
+        assert!((exponential.at(130.) - (-3f64).exp()).abs() < 1e-12);
+        assert!(exponential.at(90.) > 1.);
+
+        // given from the outside in
+        let layers = Density::layers(vec![(30., 0.1), (10., 1.), (20., 0.5)]);
+        match &layers {
+            Density::Layers(sorted) => assert_eq!(sorted, &vec![(10., 1.), (20., 0.5), (30., 0.1)]),
+            _ => unreachable!(),
+        }
+        assert_eq!(layers.at(0.), 1.);
+        assert_eq!(layers.at(9.9), 1.);
+        // a boundary belongs to the outer layer
+        assert_eq!(layers.at(10.), 0.5);
+        assert_eq!(layers.at(25.), 0.1);
+        assert_eq!(layers.at(30.), 0.);
+        assert_eq!(layers.at(1e6), 0.);
+        assert_eq!(Density::layers(vec![]).at(0.), 0.);
+    }
+
+    #[test]
+    fn layers() {
+        assert_eq!(Layers::single(0), Layers(1));
+        assert_eq!(Layers::single(31), Layers(1 << 31));
+        assert_eq!(Layers::single(2) | Layers::single(5), Layers(0b100100));
+        assert!(Layers::single(3).intersects(Layers::ALL));
+        assert!(Layers::single(3).intersects(Layers::single(3) | Layers::single(4)));
+        assert!(!Layers::single(3).intersects(Layers::single(4)));
+        assert!(!Layers::NONE.intersects(Layers::ALL));
+        assert!(!Layers::ALL.intersects(Layers::NONE));
+    }
+
+    #[test]
+    #[should_panic(expected = "Collision layer 32 out of range")]
+    fn layer_out_of_range() {
+        let _ = Layers::single(32);
+    }
+
+    #[test]
+    fn collides_with() {
+        use specs::{Builder, WorldExt};
+
+        let mut world = specs::World::new();
+        let parent = world.create_entity().build();
+        let bb = |layers, mask| {
+            BoundingBox::new(
+                Shape::Point,
+                Variant::Solid(units::Elasticity(1.)),
+                parent,
+                units::Displace(Vector2::zeros()),
+            )
+            .with_layers(layers, mask)
+        };
+        let (ships, shots, debris) = (Layers::single(0), Layers::single(1), Layers::single(2));
+
+        let default = BoundingBox::new(
+            Shape::Point,
+            Variant::Solid(units::Elasticity(1.)),
+            parent,
+            units::Displace(Vector2::zeros()),
+        );
+        assert!(default.collides_with(&default));
+
+        // both sides must accept each other
+        let ship = bb(ships, Layers::ALL);
+        let sensor = bb(shots, debris);
+        assert!(!ship.collides_with(&sensor));
+        assert!(!sensor.collides_with(&ship));
+        let rock = bb(debris, Layers::ALL);
+        assert!(sensor.collides_with(&rock));
+        assert!(rock.collides_with(&sensor));
+
+        // a BB in no layers or with an empty mask never collides
+        for ghost in &[bb(Layers::NONE, Layers::ALL), bb(Layers::ALL, Layers::NONE)] {
+            for other in &[&default, &ship, &rock, ghost] {
+                assert!(!ghost.collides_with(other));
+                assert!(!other.collides_with(ghost));
+            }
+        }
+
+        // friendly fire: shots pass through ships and each other, but hit debris
+        let shot = bb(shots, debris);
+        assert!(!shot.collides_with(&ship));
+        assert!(!shot.collides_with(&shot));
+        assert!(shot.collides_with(&rock));
+        assert!(ship.collides_with(&rock));
+        assert!(ship.collides_with(&ship));
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::{HashMap, HashSet};
+
+use shrev::{EventChannel, ReaderId};
+use specs::{Entity, Read, ReadExpect, ReadStorage, SystemData, World, WriteStorage};
+use units::Direction;
+
+use super::bb::{Density, Variant};
+use super::event::CollisionParty;
+use super::{BoundingBox, Event};
+use crate::{Body, BodyIndex, Inertia, Star};
+
+/// The system dragging bodies through fluids, e.g. atmospheres.
+///
+/// For each `Event::Intersect` between a `Variant::Fluid` bounding box and a non-fluid BB,
+/// the parent body of the non-fluid BB is decelerated against its velocity relative to the fluid.
+/// The drag force is the `units::Drag` of the fluid scaled by its `Density` at the dragged BB,
+/// multiplied by the relative speed squared and `Shape::drag` in the direction of motion.
+/// The velocity changes from all fluids a body is in are summed before they are clamped
+/// to its lowest speed relative to these fluids,
+/// so drag never reverses the relative velocity within a tick, even where fluids overlap.
+///
+/// An orbiting body is turned into an accelerating body while it is dragged,
+/// and this system moves it by one tick every tick until it leaves all fluids,
+/// when it follows an orbit again.
+/// Bodies that were already accelerating are only decelerated,
+/// and are expected to be moved by the system that accelerates them.
+/// Standing bodies, stars and bodies without `Inertia` are not dragged.
+#[derive(Default)]
+pub struct DragSystem {
+    reader: Option<ReaderId<Event>>,
+    /// The bodies turned into accelerating bodies by this system.
+    dragged: HashSet<Entity>,
+}
+
+impl<'a> specs::System<'a> for DragSystem {
+    type SystemData = (
+        Read<'a, EventChannel<Event>>,
+        ReadExpect<'a, units::GameInstant>,
+        WriteStorage<'a, Body>,
+        WriteStorage<'a, Star>,
+        ReadStorage<'a, Inertia>,
+        ReadStorage<'a, BoundingBox>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventChannel<Event>>().register_reader());
+    }
+
+    fn run(
+        &mut self,
+        (col_events, t, mut store_body, mut store_star, store_inertia, store_bb): Self::SystemData,
+    ) {
+        let t = *t;
+        let reader = self.reader.as_mut().expect("DragSystem was not set up");
+
+        // the star each dragged body is dragged in, its total velocity change,
+        // and its lowest speed relative to the fluids it is in
+        let mut drags: HashMap<Entity, (Entity, units::Velocity, f64)> = HashMap::new();
+        for event in col_events.read(reader) {
+            let (star, parties) = match event {
+                Event::Intersect { star, parties, .. } => (*star, parties),
+                Event::Void { .. } => continue,
+            };
+            let (fluid, subject, drag) =
+                match (parties[0].bb().variant(), parties[1].bb().variant()) {
+                    (Variant::Fluid(drag, density), Variant::Solid(_)) => (
+                        &parties[0],
+                        &parties[1],
+                        drag_at(*drag, density, &parties[1], &parties[0]),
+                    ),
+                    (Variant::Solid(_), Variant::Fluid(drag, density)) => (
+                        &parties[1],
+                        &parties[0],
+                        drag_at(*drag, density, &parties[0], &parties[1]),
+                    ),
+                    _ => continue,
+                };
+            let entity = subject.bb().parent();
+            if entity == star {
+                continue;
+            }
+            let mass = match store_inertia.get(entity) {
+                Some(inertia) => inertia.mass(),
+                None => continue,
+            };
+
+            let speed = (subject.velocity() - fluid.velocity()).0 .0.norm();
+            let delta_v = drag_delta(fluid, subject, drag, mass);
+            let entry =
+                drags
+                    .entry(entity)
+                    .or_insert((star, units::Velocity::default(), f64::INFINITY));
+            entry.1 = entry.1 + delta_v;
+            entry.2 = entry.2.min(speed);
+        }
+
+        // computed on the first insertion, which is rare
+        let mut extents = None;
+        for (&entity, &(star, delta_v, speed)) in &drags {
+            let delta_v = clamp(delta_v, speed);
+            let m = store_star
+                .get(star)
+                .expect("Collision star without a Star component")
+                .strength();
+            let body = store_body
+                .get_mut(entity)
+                .expect("Collision party without a Body component");
+            let converted = match body {
+                Body::Orbiting(orbiting) => {
+                    *body = Body::Accelerating(orbiting.to_accelerating(t, m));
+                    let _ = self.dragged.insert(entity);
+                    true
+                }
+                Body::Accelerating(_) => false,
+                Body::Root(_) | Body::Standing(_) => continue,
+            };
+            if converted {
+                // indexed with its state at `t`, before it advances to the next tick
+                store_star
+                    .get_mut(star)
+                    .expect("Collision star without a Star component")
+                    .index_mut()
+                    .insert(
+                        entity,
+                        &store_body,
+                        extents.get_or_insert_with(|| BodyIndex::extents(&store_bb)),
+                        t,
+                    );
+            }
+            let body = store_body
+                .get_mut(entity)
+                .expect("Collision party without a Body component");
+            if let Body::Accelerating(accel) = body {
+                let _ = accel.set_velocity(accel.velocity() + delta_v);
+                if self.dragged.contains(&entity) {
+                    accel.advance(t, m);
+                }
+            }
+        }
+
+        // bodies that left all fluids follow orbits again
+        let released: Vec<Entity> = self
+            .dragged
+            .iter()
+            .copied()
+            .filter(|entity| !drags.contains_key(entity))
+            .collect();
+        for entity in released {
+            let _ = self.dragged.remove(&entity);
+            let body = match store_body.get_mut(entity) {
+                Some(body) => body,
+                None => continue, // deleted
+            };
+            let accel = match body {
+                Body::Accelerating(accel) => accel,
+                _ => continue,
+            };
+            let parent = accel.parent();
+            let star = store_star
+                .get_mut(parent)
+                .expect("Body parent without a Star component");
+            *body = Body::Orbiting(accel.to_orbiting(t, star.strength()));
+            let extents = extents.get_or_insert_with(|| BodyIndex::extents(&store_bb));
+            star.index_mut().insert(entity, &store_body, extents, t);
+        }
+    }
+}
+
+/// Computes the drag of a fluid at the position of `subject`,
+/// where `drag` and `density` are given by the `Variant::Fluid` of `fluid`.
+fn drag_at(
+    drag: units::Drag,
+    density: &Density,
+    subject: &CollisionParty,
+    fluid: &CollisionParty,
+) -> units::Drag {
+    let distance = (subject.position() - fluid.position()).0.norm();
+    units::Drag(drag.0 * density.at(distance))
+}
+
+/// Computes the velocity change of the parent body of `subject` over one tick
+/// due to the drag of `fluid`.
+///
+/// `mass` is the mass of the parent body of `subject`.
+/// The result is not clamped, so it may exceed the relative speed; see `clamp`.
+fn drag_delta(
+    fluid: &CollisionParty,
+    subject: &CollisionParty,
+    drag: units::Drag,
+    mass: units::Mass,
+) -> units::Velocity {
+    let relative = (subject.velocity() - fluid.velocity()).0;
+    let speed = relative.0.norm();
+    if speed <= 0. {
+        return units::Velocity::default();
+    }
+    let direction = relative.to_normal_bearing() - subject.attitude();
+    let area = subject.bb().shape().drag(units::Bearing(direction.0));
+    let force = drag.0 * speed * speed * area;
+    units::Velocity::of(relative * (-force / mass.0 / speed))
+}
+
+/// Scales down `delta_v` so that its magnitude does not exceed `speed`.
+fn clamp(delta_v: units::Velocity, speed: f64) -> units::Velocity {
+    let norm = delta_v.0 .0.norm();
+    if norm > speed {
+        units::Velocity::of(delta_v.0 * (speed / norm))
+    } else {
+        delta_v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+    use specs::{Builder, Join, RunNow, WorldExt};
+
+    use super::*;
+    use crate::collision::bb::Shape;
+    use crate::{AccelBody, Attitude, Orbit, OrbitalState, OrbitingBody};
+
+    /// Creates a world with a star of the given strength.
+    fn world(strength: f64) -> (World, Entity, DragSystem) {
+        let mut world = World::new();
+        world.register::<Body>();
+        world.register::<Star>();
+        world.register::<BoundingBox>();
+        world.register::<Attitude>();
+        world.register::<Inertia>();
+        world.insert(units::GameInstant(0));
+        world.insert(EventChannel::<Event>::new());
+        let mut drag = DragSystem::default();
+        specs::System::setup(&mut drag, &mut world);
+        let star = world
+            .create_entity()
+            .with(Star::new(1e6, units::Mass(strength)))
+            .build();
+        (world, star, drag)
+    }
+
+    /// Attaches a uniform fluid BB of radius 150 to `star`.
+    fn add_fluid(world: &mut World, star: Entity, drag: f64) -> Entity {
+        world
+            .create_entity()
+            .with(BoundingBox::new(
+                Shape::Circle { radius: 150. },
+                Variant::Fluid(units::Drag(drag), Density::Uniform),
+                star,
+                units::Displace(Vector2::zeros()),
+            ))
+            .build()
+    }
+
+    /// Attaches a solid circular BB of radius 1 to `parent`.
+    fn add_solid(world: &mut World, parent: Entity) {
+        let _ = world
+            .create_entity()
+            .with(BoundingBox::new(
+                Shape::Circle { radius: 1. },
+                Variant::Solid(units::Elasticity(1.)),
+                parent,
+                units::Displace(Vector2::zeros()),
+            ))
+            .build();
+    }
+
+    /// Runs the collision and drag systems at `t`.
+    fn run(world: &mut World, drag: &mut DragSystem, t: units::GameInstant) {
+        *world.write_resource::<units::GameInstant>() = t;
+        super::super::System.run_now(world);
+        drag.run_now(world);
+        world.maintain();
+    }
+
+    fn state(world: &World, star: Entity, entity: Entity, t: units::GameInstant) -> OrbitalState {
+        let m = world.read_storage::<Star>().get(star).unwrap().strength();
+        let store_body = world.read_storage::<Body>();
+        let body = store_body.get(entity).unwrap();
+        OrbitalState::new(body.position(t), body.velocity(t, m))
+    }
+
+    #[test]
+    fn drag_cycle() {
+        let (mut world, star, mut drag) = world(1e4);
+        let fluid = add_fluid(&mut world, star, 1e-3);
+        // a circular orbit of radius 100 inside the fluid
+        let initial = OrbitalState::new(
+            units::Position(Vector2::new(100., 0.)),
+            units::Velocity::of(units::Displace(Vector2::new(0., 10.))),
+        );
+        let orbit = Orbit::from_states(initial, units::GameInstant(0), units::Mass(1e4));
+        let body = world
+            .create_entity()
+            .with(Body::Orbiting(OrbitingBody::new(orbit, star)))
+            .with(Inertia::new(units::Mass(1.)))
+            .build();
+        add_solid(&mut world, body);
+        {
+            let store_body = world.read_storage::<Body>();
+            let extents = BodyIndex::extents(&world.read_storage::<BoundingBox>());
+            let mut store_star = world.write_storage::<Star>();
+            let index = store_star.get_mut(star).unwrap().index_mut();
+            for (entity, _) in (&world.entities(), &store_body).join() {
+                index.insert(entity, &store_body, &extents, units::GameInstant(0));
+            }
+        }
+
+        let mut speed = 10.;
+        for tick in 0..5 {
+            let t = units::GameInstant(tick);
+            run(&mut world, &mut drag, t);
+            assert!(matches!(
+                world.read_storage::<Body>().get(body),
+                Some(Body::Accelerating(_))
+            ));
+            {
+                let store_star = world.read_storage::<Star>();
+                let index = store_star.get(star).unwrap().index();
+                assert!(index.all_accelerating().any(|entity| entity == body));
+                assert!(!index.all_orbiting().any(|entity| entity == body));
+            }
+            // advanced by one tick after it is decelerated
+            let next = state(&world, star, body, t + units::GameDuration::UNIT);
+            let new_speed = next.velocity().0 .0.norm();
+            assert!(new_speed < speed, "{} >= {}", new_speed, speed);
+            speed = new_speed;
+        }
+
+        // released once the fluid is gone
+        world.delete_entity(fluid).unwrap();
+        world.maintain();
+        let t = units::GameInstant(5);
+        let before = state(&world, star, body, t);
+        run(&mut world, &mut drag, t);
+        assert!(matches!(
+            world.read_storage::<Body>().get(body),
+            Some(Body::Orbiting(_))
+        ));
+        let after = state(&world, star, body, t);
+        assert!((after.position() - before.position()).0.norm() < 1e-6);
+        assert!((after.velocity() - before.velocity()).0 .0.norm() < 1e-6);
+        let store_star = world.read_storage::<Star>();
+        let index = store_star.get(star).unwrap().index();
+        assert!(index.all_orbiting().any(|entity| entity == body));
+        assert!(!index.all_accelerating().any(|entity| entity == body));
+    }
+
+    #[test]
+    fn overlapping_fluids_clamped() {
+        let (mut world, star, mut drag) = world(1e-9);
+        // each fluid alone would stop the body within a tick
+        let _ = add_fluid(&mut world, star, 1e3);
+        let _ = add_fluid(&mut world, star, 1e3);
+        let body = world
+            .create_entity()
+            .with(Body::Accelerating(AccelBody::new(
+                units::Position(Vector2::new(100., 0.)),
+                units::Velocity::of(units::Displace(Vector2::new(5., 0.))),
+                star,
+            )))
+            .with(Inertia::new(units::Mass(1.)))
+            .build();
+        add_solid(&mut world, body);
+        {
+            let store_body = world.read_storage::<Body>();
+            let extents = BodyIndex::extents(&world.read_storage::<BoundingBox>());
+            let mut store_star = world.write_storage::<Star>();
+            let index = store_star.get_mut(star).unwrap().index_mut();
+            index.insert(body, &store_body, &extents, units::GameInstant(0));
+        }
+        run(&mut world, &mut drag, units::GameInstant(0));
+
+        // stopped rather than reversed
+        let velocity = state(&world, star, body, units::GameInstant(0)).velocity();
+        assert!(velocity.0 .0.norm() < 1e-9, "{}", velocity.0 .0);
+    }
+}
@@ -9,15 +9,32 @@ pub enum Event {
     /// Two bounding boxes (BBs) intersect.
     ///
     /// This event is only dispatched for BBs belonging to different parent bodies.
+    ///
+    /// This variant used to only have `star` and `parties`.
+    /// It now also carries the `time` of contact and the contact `normal`,
+    /// and `parties` is boxed to keep `Event` small,
+    /// so existing patterns need `..` or the new fields, and `parties` needs to be dereferenced.
     Intersect {
         /// The entity of the star system in which this collision is handled within.
         ///
         /// All positions in this event are relative to this star entity.
         star: specs::Entity,
+        /// The time of first contact, in ticks after `GameInstant::EPOCH`.
+        ///
+        /// This may be between the previous tick and the current tick
+        /// if the BBs started intersecting in between.
+        /// The positions and velocities of the parties are given at this time.
+        time: f64,
         /// The parties participating in the collision.
-        parties: [CollisionParty; 2],
+        parties: Box<[CollisionParty; 2]>,
+        /// The contact normal, i.e. the unit vector from the first party towards the second
+        /// along which the BBs are pushed apart.
+        normal: units::Displace,
     },
-    /// An entity escapes the g-field of a star.
+    /// An entity escapes the g-field of a root star.
+    ///
+    /// `soi::System` emits this once when the entity leaves the field,
+    /// not on every tick it stays outside.
     Void {
         /// The entity entering void zone
         ///
@@ -40,6 +57,9 @@ pub struct CollisionParty {
     /// The position of this party relative to the star.
     #[getset(get_copy = "pub", set = "pub")]
     position: units::Position,
+    /// The attitude of the parent body of this party.
+    #[getset(get_copy = "pub", set = "pub")]
+    attitude: units::Bearing,
     /// The velocity of this party relative to the star.
     #[getset(get_copy = "pub", set = "pub")]
     velocity: units::Velocity,
@@ -47,3 +67,22 @@ pub struct CollisionParty {
     #[getset(get_copy = "pub", set = "pub")]
     collision: units::Displace,
 }
+
+impl CollisionParty {
+    /// Constructs a collision party.
+    pub fn new(
+        bb: BoundingBox,
+        position: units::Position,
+        attitude: units::Bearing,
+        velocity: units::Velocity,
+        collision: units::Displace,
+    ) -> Self {
+        Self {
+            bb,
+            position,
+            attitude,
+            velocity,
+            collision,
+        }
+    }
+}
@@ -9,3 +9,9 @@ pub use event::Event;
 
 mod system;
 pub use system::System;
+
+mod response;
+pub use response::ResponseSystem;
+
+mod drag;
+pub use drag::DragSystem;
@@ -0,0 +1,431 @@
+use std::collections::HashSet;
+use std::mem;
+
+use shrev::{EventChannel, ReaderId};
+use specs::{Entity, Read, ReadExpect, ReadStorage, SystemData, World, WriteStorage};
+
+use super::bb::Variant;
+use super::event::CollisionParty;
+use super::{BoundingBox, Event};
+use crate::{Body, BodyIndex, Inertia, Star};
+
+/// The system deflecting colliding solid bodies.
+///
+/// For each `Event::Intersect` between two `Variant::Solid` bounding boxes,
+/// momentum is exchanged along the contact normal of the event,
+/// using the `Inertia` of the parent bodies
+/// and the product of the elasticities of the BBs as the coefficient of restitution.
+/// The bodies are then pushed apart along the same line,
+/// just far enough for the BBs to stop intersecting.
+///
+/// Bodies without `Inertia`, as well as the star in whose system the collision is handled,
+/// are immovable.
+/// The impulse is computed from the velocities at the time of contact,
+/// and applied at the current tick.
+/// Each pair of bodies is deflected at most once per tick, at its earliest contact.
+#[derive(Default)]
+pub struct ResponseSystem {
+    reader: Option<ReaderId<Event>>,
+}
+
+/// The maximum number of steps taken to push two bounding boxes apart.
+///
+/// Each step moves the BBs apart by their remaining overlap,
+/// which separates convex shapes in one step up to rounding errors.
+const PUSH_STEPS: u32 = 16;
+
+/// A body participating in a collision response.
+struct Party {
+    entity: Entity,
+    /// The reciprocal of the mass of the body, which is zero for immovable bodies.
+    inverse_mass: f64,
+    elasticity: units::Elasticity,
+}
+
+impl<'a> specs::System<'a> for ResponseSystem {
+    type SystemData = (
+        Read<'a, EventChannel<Event>>,
+        ReadExpect<'a, units::GameInstant>,
+        WriteStorage<'a, Body>,
+        WriteStorage<'a, Star>,
+        ReadStorage<'a, Inertia>,
+        ReadStorage<'a, BoundingBox>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(world.fetch_mut::<EventChannel<Event>>().register_reader());
+    }
+
+    fn run(
+        &mut self,
+        (col_events, t, mut store_body, mut store_star, store_inertia, store_bb): Self::SystemData,
+    ) {
+        let t = *t;
+        let reader = self.reader.as_mut().expect("ResponseSystem was not set up");
+
+        let mut collisions: Vec<(f64, Entity, &[CollisionParty; 2], units::Displace)> = col_events
+            .read(reader)
+            .filter_map(|event| match event {
+                Event::Intersect {
+                    star,
+                    time,
+                    parties,
+                    normal,
+                } => Some((*time, *star, &**parties, *normal)),
+                Event::Void { .. } => None,
+            })
+            .collect();
+        collisions.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut handled = HashSet::new();
+        // computed on the first insertion, which is rare
+        let mut extents = None;
+        for (_, star, parties, normal) in collisions {
+            let party = |party: &CollisionParty| {
+                let entity = party.bb().parent();
+                let elasticity = match party.bb().variant() {
+                    Variant::Solid(elasticity) => *elasticity,
+                    Variant::Fluid(..) => return None,
+                };
+                let inverse_mass = match store_inertia.get(entity) {
+                    Some(inertia) if entity != star => 1. / inertia.mass().0,
+                    _ => 0.,
+                };
+                Some(Party {
+                    entity,
+                    inverse_mass,
+                    elasticity,
+                })
+            };
+            let (first, second) = match (party(&parties[0]), party(&parties[1])) {
+                (Some(first), Some(second)) => (first, second),
+                _ => continue,
+            };
+            let total_inverse_mass = first.inverse_mass + second.inverse_mass;
+            if total_inverse_mass <= 0. {
+                continue;
+            }
+            let key = (
+                first.entity.min(second.entity),
+                first.entity.max(second.entity),
+            );
+            if !handled.insert(key) {
+                continue;
+            }
+
+            let m = store_star
+                .get(star)
+                .expect("Collision star without a Star component")
+                .strength();
+
+            // the impulse per unit mass pushing the second party away from the first
+            let relative = parties[1].velocity() - parties[0].velocity();
+            let approach = relative.0 .0.dot(&normal.0);
+            let impulse = if approach < 0. {
+                let restitution = first.elasticity.0 * second.elasticity.0;
+                -(1. + restitution) * approach / total_inverse_mass
+            } else {
+                0.
+            };
+
+            // the distance to push the BBs apart at the current tick
+            let position_of = |party: &CollisionParty| {
+                let entity = party.bb().parent();
+                let position = if entity == star {
+                    units::Position::origin()
+                } else {
+                    store_body
+                        .get(entity)
+                        .expect("Collision party without a Body component")
+                        .position(t)
+                };
+                position + party.bb().placed_offset(party.attitude())
+            };
+            let (first_position, second_position) =
+                (position_of(&parties[0]), position_of(&parties[1]));
+            let gap = |push: f64| {
+                parties[0].bb().shape().separation(
+                    first_position,
+                    parties[0].attitude(),
+                    parties[1].bb().shape(),
+                    second_position + normal * push,
+                    parties[1].attitude(),
+                )
+            };
+            // The separation changes no faster than the push,
+            // so stepping by the overlap never passes the nearest separated position,
+            // e.g. a body inside a ring is not pushed through the ring to its outside.
+            let mut separation = 0.;
+            for _ in 0..PUSH_STEPS {
+                let overlap = -gap(separation);
+                if overlap <= 0. {
+                    break;
+                }
+                separation += overlap.max(separation * f64::EPSILON);
+            }
+
+            for &(subject, sign) in &[(&first, -1.), (&second, 1.)] {
+                if subject.inverse_mass <= 0. {
+                    continue;
+                }
+                let body = store_body
+                    .get_mut(subject.entity)
+                    .expect("Collision party without a Body component");
+                let kind = mem::discriminant(&*body);
+
+                if impulse > 0. {
+                    let delta_v = normal * (sign * impulse * subject.inverse_mass);
+                    body.apply_impulse(t, units::Velocity::of(delta_v), m);
+                }
+                if separation > 0. {
+                    let share = subject.inverse_mass / total_inverse_mass;
+                    body.displace(t, normal * (sign * separation * share), m);
+                }
+
+                // a standing body lifted off its star needs to be indexed as orbiting
+                let is_orbiting = matches!(body, Body::Orbiting(_));
+                let changed = mem::discriminant(&*body) != kind;
+                let index = store_star
+                    .get_mut(star)
+                    .expect("Collision star without a Star component")
+                    .index_mut();
+                if changed {
+                    let extents = extents.get_or_insert_with(|| BodyIndex::extents(&store_bb));
+                    index.insert(subject.entity, &store_body, extents, t);
+                } else if is_orbiting {
+                    index.invalidate(subject.entity, &store_body, t);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+    use specs::{Builder, Join, RunNow, WorldExt};
+
+    use super::*;
+    use crate::collision::bb::Shape;
+    use crate::{AccelBody, Attitude, StandingBody};
+
+    /// Creates a world with a star of the given strength.
+    ///
+    /// A negligible strength lets accelerating bodies move in straight lines.
+    fn world(strength: f64) -> (World, Entity, ResponseSystem) {
+        let mut world = World::new();
+        world.register::<Body>();
+        world.register::<Star>();
+        world.register::<BoundingBox>();
+        world.register::<Attitude>();
+        world.register::<Inertia>();
+        world.insert(units::GameInstant(0));
+        world.insert(EventChannel::<Event>::new());
+        let mut response = ResponseSystem::default();
+        specs::System::setup(&mut response, &mut world);
+        let star = world
+            .create_entity()
+            .with(Star::new(1e6, units::Mass(strength)))
+            .build();
+        (world, star, response)
+    }
+
+    /// Attaches a solid circular BB of `radius` to `parent`.
+    fn add_circle(world: &mut World, parent: Entity, radius: f64, elasticity: f64) {
+        let _ = world
+            .create_entity()
+            .with(BoundingBox::new(
+                Shape::Circle { radius },
+                Variant::Solid(units::Elasticity(elasticity)),
+                parent,
+                units::Displace(Vector2::zeros()),
+            ))
+            .build();
+    }
+
+    /// Creates an accelerating body with a circular BB of radius 2.
+    fn spawn(
+        world: &mut World,
+        star: Entity,
+        position: Vector2<f64>,
+        velocity: Vector2<f64>,
+        mass: Option<f64>,
+        elasticity: f64,
+    ) -> Entity {
+        let mut builder = world
+            .create_entity()
+            .with(Body::Accelerating(AccelBody::new(
+                units::Position(position),
+                units::Velocity::of(units::Displace(velocity)),
+                star,
+            )));
+        if let Some(mass) = mass {
+            builder = builder.with(Inertia::new(units::Mass(mass)));
+        }
+        let entity = builder.build();
+        add_circle(world, entity, 2., elasticity);
+        entity
+    }
+
+    /// Indexes all bodies and runs the collision and response systems at instant 0.
+    fn run(world: &mut World, star: Entity, response: &mut ResponseSystem) {
+        let t = units::GameInstant(0);
+        {
+            let store_body = world.read_storage::<Body>();
+            let extents = BodyIndex::extents(&world.read_storage::<BoundingBox>());
+            let mut store_star = world.write_storage::<Star>();
+            let index = store_star.get_mut(star).unwrap().index_mut();
+            for (entity, _) in (&world.entities(), &store_body).join() {
+                index.insert(entity, &store_body, &extents, t);
+            }
+        }
+        super::super::System.run_now(world);
+        response.run_now(world);
+    }
+
+    fn velocity(world: &World, star: Entity, entity: Entity) -> Vector2<f64> {
+        let m = world.read_storage::<Star>().get(star).unwrap().strength();
+        world
+            .read_storage::<Body>()
+            .get(entity)
+            .unwrap()
+            .velocity(units::GameInstant(0), m)
+            .0
+             .0
+    }
+
+    fn position(world: &World, entity: Entity) -> Vector2<f64> {
+        world
+            .read_storage::<Body>()
+            .get(entity)
+            .unwrap()
+            .position(units::GameInstant(0))
+            .0
+    }
+
+    #[test]
+    fn momentum_and_restitution() {
+        for &(first_elasticity, second_elasticity) in &[(1., 1.), (0., 1.), (0.5, 0.5)] {
+            let (mut world, star, mut response) = world(1e-9);
+            let a = spawn(
+                &mut world,
+                star,
+                Vector2::new(100., 0.),
+                Vector2::new(1., 0.5),
+                Some(1.),
+                first_elasticity,
+            );
+            let b = spawn(
+                &mut world,
+                star,
+                Vector2::new(103., 0.),
+                Vector2::new(-1., 0.),
+                Some(3.),
+                second_elasticity,
+            );
+            run(&mut world, star, &mut response);
+
+            let (va, vb) = (velocity(&world, star, a), velocity(&world, star, b));
+            let momentum = va + vb * 3.;
+            assert!(
+                (momentum - Vector2::new(-2., 0.5)).norm() < 1e-9,
+                "{}",
+                momentum
+            );
+            // the tangential velocities are unchanged
+            assert!((va[1] - 0.5).abs() < 1e-9 && vb[1].abs() < 1e-9);
+            let restitution = first_elasticity * second_elasticity;
+            assert!(
+                ((vb - va)[0] - 2. * restitution).abs() < 1e-9,
+                "{} {}",
+                va,
+                vb
+            );
+
+            // the BBs are pushed apart in inverse proportion to the masses
+            let (pa, pb) = (position(&world, a), position(&world, b));
+            assert!((pb - pa).norm() >= 4. - 1e-9, "{} {}", pa, pb);
+            assert!(((100. - pa[0]) - (pb[0] - 103.) * 3.).abs() < 1e-6);
+            assert!(pa[1].abs() < 1e-9 && pb[1].abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn immovable_parties() {
+        let (mut world, star, mut response) = world(1e-9);
+        add_circle(&mut world, star, 60., 1.);
+        let falling = spawn(
+            &mut world,
+            star,
+            Vector2::new(0., 61.),
+            Vector2::new(0., -1.),
+            Some(1.),
+            0.5,
+        );
+        let anchor = spawn(
+            &mut world,
+            star,
+            Vector2::new(200., 0.),
+            Vector2::zeros(),
+            None,
+            1.,
+        );
+        let hitting = spawn(
+            &mut world,
+            star,
+            Vector2::new(203., 0.),
+            Vector2::new(-1., 0.),
+            Some(1.),
+            1.,
+        );
+        run(&mut world, star, &mut response);
+
+        // bounced off the star, which has no body to move
+        assert!((velocity(&world, star, falling) - Vector2::new(0., 0.5)).norm() < 1e-9);
+        assert!(position(&world, falling)[1] >= 62. - 1e-9);
+        // bounced off a body without inertia, which does not move
+        assert!((velocity(&world, star, hitting) - Vector2::new(1., 0.)).norm() < 1e-9);
+        assert!(position(&world, hitting)[0] >= 204. - 1e-9);
+        assert_eq!(velocity(&world, star, anchor), Vector2::zeros());
+        assert_eq!(position(&world, anchor), Vector2::new(200., 0.));
+    }
+
+    #[test]
+    fn standing_body_lifted() {
+        // strong enough for the lifted body to follow a well-conditioned orbit
+        let (mut world, star, mut response) = world(1e4);
+        let standing = world
+            .create_entity()
+            .with(Body::Standing(StandingBody::new(
+                units::Position(Vector2::new(100., 0.)),
+                star,
+            )))
+            .with(Inertia::new(units::Mass(1.)))
+            .build();
+        add_circle(&mut world, standing, 2., 1.);
+        let _ = spawn(
+            &mut world,
+            star,
+            Vector2::new(97., 0.),
+            Vector2::new(1., 0.),
+            Some(1.),
+            1.,
+        );
+        run(&mut world, star, &mut response);
+
+        assert!(matches!(
+            world.read_storage::<Body>().get(standing),
+            Some(Body::Orbiting(_))
+        ));
+        assert!(
+            (velocity(&world, star, standing) - Vector2::new(1., 0.)).norm() < 1e-6,
+            "{}",
+            velocity(&world, star, standing)
+        );
+        let store_star = world.read_storage::<Star>();
+        let index = store_star.get(star).unwrap().index();
+        assert!(index.all_orbiting().any(|entity| entity == standing));
+        assert!(!index.all_standing().any(|entity| entity == standing));
+    }
+}
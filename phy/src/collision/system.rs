@@ -1,14 +1,601 @@
+use std::collections::HashMap;
+
 use shrev::EventChannel;
+use specs::{Entities, Entity, Join, ReadExpect, ReadStorage, Write, WriteStorage};
 
-use super::Event;
+use super::bb::Layers;
+use super::event::CollisionParty;
+use super::{BoundingBox, Event};
+use crate::predict::Motion;
+use crate::{search, Attitude, Body, OrbitalState, Star};
 
 /// The system handling collisions.
+///
+/// Every tick, the `BodyIndex` of each star is updated,
+/// and the bounding boxes of bodies that may have come into contact since the previous tick
+/// are tested pairwise.
+/// Bodies are also tested against the bounding boxes of the star itself,
+/// which is at the origin of its own star system.
+///
+/// Collisions are detected continuously:
+/// bounding boxes that start to intersect between two ticks are reported
+/// at the time of first contact, even if they have passed through each other by the next tick.
+/// Orbiting bodies are followed along their orbits,
+/// and other bodies are assumed to have moved at their current velocity since the previous tick.
+///
+/// Pairs of bodies are skipped before their motions are compared
+/// if the collision layers and masks of their bounding boxes exclude each other,
+/// as are pairs of bounding boxes before any narrow phase test.
 pub struct System;
 
 impl<'a> specs::System<'a> for System {
-    type SystemData = (specs::Write<'a, EventChannel<Event>>, specs::Entities<'a>);
+    type SystemData = (
+        Write<'a, EventChannel<Event>>,
+        Entities<'a>,
+        ReadExpect<'a, units::GameInstant>,
+        ReadStorage<'a, Body>,
+        WriteStorage<'a, Star>,
+        ReadStorage<'a, BoundingBox>,
+        ReadStorage<'a, Attitude>,
+    );
+
+    fn run(
+        &mut self,
+        (mut col_events, entities, t, store_body, mut store_star, store_bb, store_attitude): Self::SystemData,
+    ) {
+        let t = *t;
+        let time = f64::from(t.0);
+        let attitude = |entity| {
+            store_attitude
+                .get(entity)
+                .map_or(units::Bearing(0.), |attitude| attitude.bearing())
+        };
+
+        // the bounding boxes of each body
+        let mut bbs: HashMap<Entity, Vec<&BoundingBox>> = HashMap::new();
+        for bb in (&store_bb).join() {
+            bbs.entry(bb.parent()).or_default().push(bb);
+        }
+        // the unions of the collision layers and masks of the bounding boxes of each body
+        let layers: HashMap<Entity, (Layers, Layers)> = bbs
+            .iter()
+            .map(|(&entity, bbs)| {
+                let union = bbs
+                    .iter()
+                    .fold((Layers::NONE, Layers::NONE), |(layers, mask), bb| {
+                        (layers | bb.layers(), mask | bb.mask())
+                    });
+                (entity, union)
+            })
+            .filter(|&(_, (layers, mask))| layers != Layers::NONE && mask != Layers::NONE)
+            .collect();
+        // whether any bounding box of `first` may collide with any bounding box of `second`
+        let may_collide = |first, second| match (layers.get(&first), layers.get(&second)) {
+            (Some(&(first_layers, first_mask)), Some(&(second_layers, second_mask))) => {
+                first_mask.intersects(second_layers) && second_mask.intersects(first_layers)
+            }
+            _ => false,
+        };
+
+        for (star_entity, star) in (&entities, &mut store_star).join() {
+            let m = star.strength();
+            let index = star.index_mut();
+            index.update(&store_body, t);
+
+            // bodies that collide with nothing need no motion
+            let mut motions: HashMap<Entity, Motion> = index
+                .all()
+                .filter(|entity| layers.contains_key(entity))
+                .map(|entity| {
+                    let body = store_body
+                        .get(entity)
+                        .expect("Indexed entity without a Body component");
+                    (entity, Motion::of(body, t, m))
+                })
+                .collect();
+            // the distance each body may have travelled since the previous tick
+            let travel = |motion: &Motion| if t.0 == 0 { 0. } else { motion.max_speed(m) };
+
+            let mut pairs = index.pairs(&store_body, t, |entity| {
+                motions.get(&entity).map_or(0., travel)
+            });
+            if let Some(star_bbs) = bbs.get(&star_entity) {
+                let reach = star_bbs
+                    .iter()
+                    .map(|bb| bb.offset().0.norm() + bb.shape().radius())
+                    .fold(0., f64::max);
+                let max_travel = motions.values().map(travel).fold(0., f64::max);
+                pairs.extend(
+                    index
+                        .below(&store_body, reach + max_travel, t)
+                        .map(|entity| (star_entity, entity)),
+                );
+            }
+            pairs.retain(|&(first, second)| may_collide(first, second));
+            let _ = motions.insert(
+                star_entity,
+                Motion::Linear {
+                    state: OrbitalState::new(units::Position::origin(), units::Velocity::default()),
+                    since: time,
+                },
+            );
+
+            for (first, second) in pairs {
+                let (first_bbs, second_bbs) = match (bbs.get(&first), bbs.get(&second)) {
+                    (Some(first_bbs), Some(second_bbs)) => (first_bbs, second_bbs),
+                    _ => continue,
+                };
+                let (first_motion, second_motion) = (&motions[&first], &motions[&second]);
+                let (first_attitude, second_attitude) = (attitude(first), attitude(second));
+                let approach = travel(first_motion) + travel(second_motion);
+
+                for &first_bb in first_bbs {
+                    for &second_bb in second_bbs {
+                        if !first_bb.collides_with(second_bb) {
+                            continue;
+                        }
+                        let toi = match time_of_impact(
+                            (first_bb, first_motion, first_attitude),
+                            (second_bb, second_motion, second_attitude),
+                            approach,
+                            time,
+                        ) {
+                            Some(toi) => toi,
+                            None => continue,
+                        };
+
+                        let first_state = first_motion.state_at(toi, m);
+                        let second_state = second_motion.state_at(toi, m);
+                        let first_position =
+                            first_state.position() + first_bb.placed_offset(first_attitude);
+                        let second_position =
+                            second_state.position() + second_bb.placed_offset(second_attitude);
+                        let contact = first_bb.shape().contact(
+                            first_position,
+                            first_attitude,
+                            second_bb.shape(),
+                            second_position,
+                            second_attitude,
+                        );
+                        if let Some((contact, normal)) = contact {
+                            col_events.single_write(Event::Intersect {
+                                star: star_entity,
+                                time: toi,
+                                parties: Box::new([
+                                    CollisionParty::new(
+                                        first_bb.clone(),
+                                        first_position,
+                                        first_attitude,
+                                        first_state.velocity(),
+                                        contact - first_position,
+                                    ),
+                                    CollisionParty::new(
+                                        second_bb.clone(),
+                                        second_position,
+                                        second_attitude,
+                                        second_state.velocity(),
+                                        contact - second_position,
+                                    ),
+                                ]),
+                                normal,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Finds the time at which two bounding boxes, moving along their parents' motions,
+/// intersect during the tick ending at `time`.
+///
+/// `travel` is an upper bound of the distance the two BBs approach each other during the tick.
+/// The attitudes of the bodies are assumed to be constant during the tick.
+///
+/// BBs that already intersected at the previous tick are reported at `time` if they still do,
+/// otherwise the first contact in `(time - 1, time]` is returned.
+///
+/// If the BBs are apart at both ends of the tick,
+/// their closest approach is searched with `search::minimize`,
+/// which assumes the separation first decreases and then increases within the tick.
+/// This holds for BBs passing each other once,
+/// but BBs approaching each other twice within one tick (e.g. on tight orbits)
+/// may only be tested at one of the approaches.
+fn time_of_impact(
+    (first_bb, first_motion, first_attitude): (&BoundingBox, &Motion, units::Bearing),
+    (second_bb, second_motion, second_attitude): (&BoundingBox, &Motion, units::Bearing),
+    travel: units::Length,
+    time: f64,
+) -> Option<f64> {
+    let threshold = first_bb.shape().radius() + second_bb.shape().radius();
+    if threshold <= 0. {
+        return None; // two points never collide
+    }
+    let (first_offset, second_offset) = (
+        first_bb.placed_offset(first_attitude),
+        second_bb.placed_offset(second_attitude),
+    );
+    let first_position = |time| first_motion.position_at(time) + first_offset;
+    let second_position = |time| second_motion.position_at(time) + second_offset;
+
+    // the enclosing circles are apart during the whole tick
+    let end_distance = (first_position(time) - second_position(time)).0.norm();
+    if end_distance - threshold - travel >= 0. {
+        return None;
+    }
+
+    let gap = |time: f64| {
+        first_bb.shape().separation(
+            first_position(time),
+            first_attitude,
+            second_bb.shape(),
+            second_position(time),
+            second_attitude,
+        )
+    };
+    let end_gap = gap(time);
+    if end_gap - travel >= 0. {
+        return None;
+    }
+    let previous = time - 1.;
+    if travel <= 0. || gap(previous) < 0. {
+        return if end_gap < 0. { Some(time) } else { None };
+    }
+
+    let closest = if end_gap < 0. {
+        time
+    } else {
+        search::minimize(gap, previous, time)
+    };
+    if gap(closest) < 0. {
+        let (_, toi) = search::bisect(|time| gap(time) < 0., previous, closest);
+        Some(toi)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use nalgebra::Vector2;
+    use shrev::ReaderId;
+    use specs::{Builder, RunNow, World, WorldExt};
+
+    use super::*;
+    use crate::collision::bb::{Shape, Variant};
+    use crate::{AccelBody, BodyIndex};
+
+    /// A summary of an `Event::Intersect`.
+    #[derive(Debug)]
+    struct Hit {
+        time: f64,
+        parents: [Entity; 2],
+        contact: units::Position,
+        normal: units::Displace,
+    }
+
+    /// Creates a world with a star of negligible strength,
+    /// so that bodies move in straight lines.
+    fn world() -> (World, Entity, ReaderId<Event>) {
+        let mut world = World::new();
+        world.register::<Body>();
+        world.register::<Star>();
+        world.register::<BoundingBox>();
+        world.register::<Attitude>();
+        world.insert(units::GameInstant(0));
+        let mut channel = EventChannel::<Event>::new();
+        let reader = channel.register_reader();
+        world.insert(channel);
+        let star = world
+            .create_entity()
+            .with(Star::new(1e6, units::Mass(1e-9)))
+            .build();
+        (world, star, reader)
+    }
+
+    /// Creates a body at `position` moving at `velocity` and facing `attitude`,
+    /// with a BB of `shape` at `offset`.
+    fn spawn(
+        world: &mut World,
+        star: Entity,
+        position: Vector2<f64>,
+        velocity: Vector2<f64>,
+        attitude: f64,
+        shape: Shape,
+        offset: Vector2<f64>,
+    ) -> Entity {
+        let entity = world
+            .create_entity()
+            .with(Body::Accelerating(AccelBody::new(
+                units::Position(position),
+                units::Velocity::of(units::Displace(velocity)),
+                star,
+            )))
+            .with(Attitude::new(units::Bearing(attitude)))
+            .build();
+        add_bb(world, entity, shape, offset);
+        entity
+    }
+
+    fn add_bb(world: &mut World, parent: Entity, shape: Shape, offset: Vector2<f64>) {
+        let _ = world
+            .create_entity()
+            .with(BoundingBox::new(
+                shape,
+                Variant::Solid(units::Elasticity(1.)),
+                parent,
+                units::Displace(offset),
+            ))
+            .build();
+    }
+
+    /// Indexes all bodies and runs the system at tick `t`, returning the collisions.
+    fn run(world: &mut World, star: Entity, reader: &mut ReaderId<Event>, t: u32) -> Vec<Hit> {
+        let t = units::GameInstant(t);
+        *world.write_resource::<units::GameInstant>() = t;
+        {
+            let store_body = world.read_storage::<Body>();
+            let extents = BodyIndex::extents(&world.read_storage::<BoundingBox>());
+            let mut store_star = world.write_storage::<Star>();
+            let index = store_star.get_mut(star).unwrap().index_mut();
+            for (entity, _) in (&world.entities(), &store_body).join() {
+                index.insert(entity, &store_body, &extents, t);
+            }
+        }
+        System.run_now(world);
+
+        let channel = world.read_resource::<EventChannel<Event>>();
+        channel
+            .read(reader)
+            .map(|event| match event {
+                Event::Intersect {
+                    star: event_star,
+                    time,
+                    parties,
+                    normal,
+                } => {
+                    assert_eq!(*event_star, star);
+                    Hit {
+                        time: *time,
+                        parents: [parties[0].bb().parent(), parties[1].bb().parent()],
+                        contact: parties[0].position() + parties[0].collision(),
+                        normal: *normal,
+                    }
+                }
+                Event::Void { .. } => panic!("unexpected void event"),
+            })
+            .collect()
+    }
+
+    fn still(
+        world: &mut World,
+        star: Entity,
+        x: f64,
+        y: f64,
+        attitude: f64,
+        shape: Shape,
+        offset: Vector2<f64>,
+    ) -> Entity {
+        spawn(
+            world,
+            star,
+            Vector2::new(x, y),
+            Vector2::zeros(),
+            attitude,
+            shape,
+            offset,
+        )
+    }
+
+    #[test]
+    fn broad_phase_pairs() {
+        let (mut world, star, mut reader) = world();
+        let circle = Shape::Circle { radius: 2. };
+        let a = still(
+            &mut world,
+            star,
+            100.,
+            0.,
+            0.,
+            circle.clone(),
+            Vector2::zeros(),
+        );
+        let b = still(
+            &mut world,
+            star,
+            103.,
+            0.,
+            0.,
+            circle.clone(),
+            Vector2::zeros(),
+        );
+        // too far from both
+        let _ = still(&mut world, star, 110., 0., 0., circle, Vector2::zeros());
+
+        let hits = run(&mut world, star, &mut reader, 0);
+        assert_eq!(hits.len(), 1, "{:?}", hits);
+        let hit = &hits[0];
+        let mut parents = hit.parents;
+        parents.sort();
+        assert_eq!(parents, [a, b]);
+        assert_eq!(hit.time, 0.);
+        assert!((hit.contact.0[0] - 101.5).abs() <= 0.5 + 1e-9);
+        let towards = if hit.parents[0] == a { 1. } else { -1. };
+        assert!((hit.normal.0 - Vector2::new(towards, 0.)).norm() < 1e-9);
+    }
+
+    #[test]
+    fn star_bounding_boxes() {
+        let (mut world, star, mut reader) = world();
+        add_bb(
+            &mut world,
+            star,
+            Shape::Circle { radius: 60. },
+            Vector2::zeros(),
+        );
+        let inside = still(
+            &mut world,
+            star,
+            0.,
+            58.,
+            0.,
+            Shape::Point,
+            Vector2::zeros(),
+        );
+        let grazing = still(
+            &mut world,
+            star,
+            -61.,
+            0.,
+            0.,
+            Shape::Circle { radius: 2. },
+            Vector2::zeros(),
+        );
+        let _ = still(
+            &mut world,
+            star,
+            0.,
+            -70.,
+            0.,
+            Shape::Circle { radius: 2. },
+            Vector2::zeros(),
+        );
+
+        let hits = run(&mut world, star, &mut reader, 0);
+        let mut pairs: Vec<[Entity; 2]> = hits.iter().map(|hit| hit.parents).collect();
+        pairs.sort();
+        let mut expected = vec![[star, inside], [star, grazing]];
+        expected.sort();
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn rotated_offsets() {
+        let (mut world, star, mut reader) = world();
+        let offset = Vector2::new(10., 0.);
+        // facing north, so the BB is 10 units north of the body
+        let a = still(
+            &mut world,
+            star,
+            100.,
+            0.,
+            PI / 2.,
+            Shape::Circle { radius: 1. },
+            offset,
+        );
+        let north = still(
+            &mut world,
+            star,
+            100.,
+            11.5,
+            0.,
+            Shape::Circle { radius: 1. },
+            Vector2::zeros(),
+        );
+        // where the BB would be without rotation
+        let _ = still(
+            &mut world,
+            star,
+            111.5,
+            0.,
+            0.,
+            Shape::Circle { radius: 1. },
+            Vector2::zeros(),
+        );
+
+        let hits = run(&mut world, star, &mut reader, 0);
+        assert_eq!(hits.len(), 1, "{:?}", hits);
+        let mut parents = hits[0].parents;
+        parents.sort();
+        let mut expected = [a, north];
+        expected.sort();
+        assert_eq!(parents, expected);
+        assert!((hits[0].contact.0 - Vector2::new(100., 10.75)).norm() <= 0.25 + 1e-9);
+    }
+
+    #[test]
+    fn tunnelling() {
+        let (mut world, star, mut reader) = world();
+        let wall = still(
+            &mut world,
+            star,
+            1000.,
+            0.,
+            0.,
+            Shape::Circle { radius: 5. },
+            Vector2::zeros(),
+        );
+        // at (1000, -50) in the previous tick, and past the circle in this tick
+        let bullet = spawn(
+            &mut world,
+            star,
+            Vector2::new(1000., 100.),
+            Vector2::new(0., 150.),
+            0.,
+            Shape::Point,
+            Vector2::zeros(),
+        );
+
+        let hits = run(&mut world, star, &mut reader, 1);
+        assert_eq!(hits.len(), 1, "{:?}", hits);
+        let hit = &hits[0];
+        let mut parents = hit.parents;
+        parents.sort();
+        let mut expected = [wall, bullet];
+        expected.sort();
+        assert_eq!(parents, expected);
+        assert!(0. < hit.time && hit.time < 1., "{}", hit.time);
+        assert!((hit.time - 0.3).abs() < 1e-6, "{}", hit.time);
+        let radius = (hit.contact.0 - Vector2::new(1000., 0.)).norm();
+        assert!((radius - 5.).abs() < 1e-6, "{:?}", hit.contact);
+        assert!(hit.contact.0[1] < 0.);
+    }
+
+    #[test]
+    fn layer_filtering() {
+        use crate::collision::bb::Layers;
+
+        let (mut world, star, mut reader) = world();
+        let (team, shots, enemy) = (Layers::single(0), Layers::single(1), Layers::single(2));
+        let mut spawn_layered = |x: f64, layers, mask| {
+            let entity = world
+                .create_entity()
+                .with(Body::Accelerating(AccelBody::new(
+                    units::Position(Vector2::new(x, 0.)),
+                    units::Velocity::default(),
+                    star,
+                )))
+                .build();
+            let _ = world
+                .create_entity()
+                .with(
+                    BoundingBox::new(
+                        Shape::Circle { radius: 1.5 },
+                        Variant::Solid(units::Elasticity(1.)),
+                        entity,
+                        units::Displace(Vector2::zeros()),
+                    )
+                    .with_layers(layers, mask),
+                )
+                .build();
+            entity
+        };
+        // the shot overlaps both ships, which are apart,
+        // but is fired by the first ship and does not hit its own team
+        let _ = spawn_layered(100., team, Layers::ALL);
+        let shot = spawn_layered(102., shots, enemy);
+        let target = spawn_layered(104., enemy, Layers::ALL);
+        let _ = spawn_layered(102., Layers::NONE, Layers::ALL);
 
-    fn run(&mut self, (col_events, entities): Self::SystemData) {
-        todo!()
+        let hits = run(&mut world, star, &mut reader, 0);
+        assert_eq!(hits.len(), 1, "{:?}", hits);
+        let mut parents = hits[0].parents;
+        parents.sort();
+        let mut expected = [shot, target];
+        expected.sort();
+        assert_eq!(parents, expected);
     }
 }
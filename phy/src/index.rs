@@ -1,73 +1,2076 @@
-use specs::{Entity, WriteStorage};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::f64::consts::PI;
+use std::ops::{Deref, Range};
+
+use specs::storage::{GenericReadStorage, MaskedStorage};
+use specs::{Entity, Join, Storage};
+use units::Direction;
 
 use super::collision::BoundingBox;
+use super::{Body, Orbit};
+
+/// The queue is compacted when it exceeds this multiple of the number of valid certificates.
+const COMPACT_RATIO: usize = 4;
+
+/// `update()` switches to re-sorting once it checks more certificates in one tick
+/// than this fraction of the orbiting bodies (and at least `RESORT_MIN_CHECKS`).
+///
+/// Checking a certificate costs a few times as much as computing the sort keys of a body.
+const RESORT_FRACTION: f64 = 0.25;
+
+/// The minimum number of certificates checked in one tick before `update()` switches to re-sorting.
+const RESORT_MIN_CHECKS: usize = 64;
+
+/// `update()` switches back to certificates once re-sorting moves
+/// no more than this fraction of the orbiting bodies in one tick.
+const KINETIC_FRACTION: f64 = 1. / 64.;
+
+/// Bodies closer to the star than this multiple of the largest extent
+/// are not looked up by bearing, since they may overlap a wide range of bearings.
+const CUT_RATIO: f64 = 4.;
 
 /// A data structure to store a set of bodies.
 ///
 /// This data structure needs to be `update()`d every tick.
-/// For the ordinary case, updating is NOT O(n).
-#[derive(Debug)]
+///
+/// Orbiting bodies are kept sorted by radius and by bearing.
+/// For each pair of adjacent bodies in either order,
+/// a certificate is scheduled at a lower bound of the instant they may swap
+/// (computed from `Orbit::when_intersect_radius` and `Orbit::when_intersect_bearing`).
+/// `update()` only checks the certificates that are due,
+/// swapping the pairs that have actually crossed and rescheduling the others.
+///
+/// The cost of checking certificates is proportional to the number of swaps,
+/// not to the number of bodies, which is cheap for a few bodies with similar periods.
+/// However, bodies at different altitudes overtake each other in the angular order,
+/// and bodies on crossing orbits also swap in the radial order,
+/// so the number of swaps per tick grows quadratically with the number of bodies.
+/// When too many certificates are due in one tick,
+/// `update()` instead re-sorts both orders from the current radii and bearings every tick,
+/// which is O(n log n) (and close to O(n) since the orders are nearly sorted),
+/// and returns to certificates once the orders change slowly again.
+/// The `index` benchmark measures both cases with up to 10k bodies.
+///
+/// The bearing order is cyclic.
+/// It is stored linearly from the zero bearing counterclockwise (as in `Orbit::compare_bearing`),
+/// and bodies crossing the zero bearing are moved between the two ends.
+/// To keep the sort keys continuous in time,
+/// each body is sorted by its unwrapped bearing minus a whole number of revolutions,
+/// which is only adjusted when the body is moved between the two ends.
+///
+/// Accelerating bodies cannot be predicted analytically,
+/// so they are re-sorted by radius from their current positions in every `update()`
+/// and swept by radius in queries.
+///
+/// Each body is indexed with an extent enclosing all its bounding boxes,
+/// and queries return the bodies whose extent overlaps the queried range.
+///
+/// The index keeps a copy of the orbit of each orbiting body,
+/// so removed orbiting bodies stay in the orders as tombstones following their last orbit,
+/// even if the entity has been despawned.
+/// Tombstones are skipped by queries and dropped once they outnumber the other orbiting bodies.
+#[derive(Debug, Default)]
 pub struct BodyIndex {
     // orbiting
     radial: Vec<Entity>,
-    angular: Vec<Entity>,
+    angular: VecDeque<Entity>,
+
+    // accelerating
+    accelerating: BTreeSet<Entity>,
+    /// The accelerating entities sorted by radius, as of the last update.
+    ///
+    /// Entries of removed entities are left until the next update.
+    accel_sorted: Vec<AccelEntry>,
 
     // standing
-    standing: Vec<Entity>,
+    standing: BTreeSet<Entity>,
+
+    /// The orbit of each orbiting entity in `radial` and `angular`.
+    orbits: HashMap<Entity, Orbit>,
+    /// The removed entities still in `radial` and `angular`.
+    removed: HashSet<Entity>,
+    /// The position of each orbiting entity in `radial`.
+    radial_rank: HashMap<Entity, usize>,
+    /// The position and winding of each orbiting entity in `angular`.
+    angular_slot: HashMap<Entity, AngularSlot>,
+    /// The rank of the first entity in `angular`.
+    angular_base: i64,
+
+    /// The radius of the circle enclosing all bounding boxes of each entity, around its position.
+    extents: HashMap<Entity, units::Length>,
+    /// The number of entities with each extent.
+    ///
+    /// Extents are non-negative, so they are keyed by their bits, which are ordered alike.
+    extent_counts: BTreeMap<u64, usize>,
+
+    /// Scheduled certificates, earliest first.
+    queue: BinaryHeap<Reverse<Certificate>>,
+    /// The version of the only valid certificate for each key.
+    versions: HashMap<Key, u64>,
+    /// The version for the next scheduled certificate.
+    next_version: u64,
+    /// Whether the orders are re-sorted in every update instead of maintained by certificates.
+    ///
+    /// No certificates are scheduled while this is set.
+    resorting: bool,
+}
+
+/// An accelerating entity with its position as of the last update.
+#[derive(Debug, Clone, Copy)]
+struct AccelEntry {
+    radius: units::Length,
+    position: units::Position,
+    entity: Entity,
+}
+
+/// The position of an entity in the bearing order.
+#[derive(Debug, Clone, Copy)]
+struct AngularSlot {
+    /// The position in `angular`, offset by `angular_base`.
+    rank: i64,
+    /// The number of revolutions subtracted from the unwrapped bearing to get the sort key.
+    winding: i64,
+}
+
+/// Identifies what a certificate checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    /// The entity and its successor in `radial` are in order.
+    Radial(Entity),
+    /// The entity and its successor in `angular` are in order.
+    Angular(Entity),
+    /// The first entity in `angular` has not crossed the zero bearing clockwise.
+    Front,
+    /// The last entity in `angular` has not crossed the zero bearing counterclockwise.
+    Back,
+}
+
+/// A scheduled check of the ordering.
+///
+/// The ordering identified by `key` is guaranteed to hold until `due`.
+#[derive(Debug)]
+struct Certificate {
+    due: units::GameInstant,
+    version: u64,
+    key: Key,
+}
+
+impl PartialEq for Certificate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Certificate {}
+
+impl PartialOrd for Certificate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Certificate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due, self.version).cmp(&(other.due, other.version))
+    }
+}
+
+/// A counterclockwise arc of bearings.
+#[derive(Debug, Clone, Copy)]
+struct Arc {
+    /// The bearing at which the arc starts, in `[0, 2pi)`.
+    start: f64,
+    /// The angle covered by the arc, in `[0, 2pi]`.
+    length: f64,
+}
+
+impl Arc {
+    /// The arc covering all bearings.
+    const WHOLE: Self = Self {
+        start: 0.,
+        length: PI * 2.,
+    };
+
+    /// Constructs the arc from `from` counterclockwise to `to`.
+    fn new(from: units::Bearing, to: units::Bearing) -> Self {
+        Self::with_length(from.0, normalize((to - from).0))
+    }
+
+    /// Constructs the arc from `start` counterclockwise covering `length`.
+    fn with_length(start: f64, length: f64) -> Self {
+        if length >= PI * 2. {
+            Self::WHOLE
+        } else {
+            Self {
+                start: normalize(start),
+                length,
+            }
+        }
+    }
+
+    /// Extends the arc by `margin` on both sides.
+    fn widen(self, margin: f64) -> Self {
+        Self::with_length(self.start - margin, self.length + margin * 2.)
+    }
+
+    /// Computes the angle from a bearing to the nearest bearing in the arc.
+    fn distance(self, bearing: f64) -> f64 {
+        let offset = normalize(bearing - self.start);
+        if offset <= self.length {
+            0.
+        } else {
+            (offset - self.length).min(PI * 2. - offset)
+        }
+    }
+}
+
+/// An annular sector around the parent star.
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    min_radius: units::Length,
+    max_radius: units::Length,
+    arc: Arc,
+}
+
+impl Region {
+    /// Checks whether a circle at `position` with radius `extent` may overlap this region.
+    ///
+    /// The radial bounds are exact, but the circle is treated as the wedge enclosing it,
+    /// so circles just outside the corners of the region are also accepted.
+    fn overlaps(self, position: units::Position, extent: units::Length) -> bool {
+        let radius = position.0.norm();
+        radius - extent <= self.max_radius
+            && radius + extent >= self.min_radius
+            && (radius <= extent
+                || self.arc.distance(normal_bearing(position)) <= (extent / radius).asin())
+    }
 }
 
 impl BodyIndex {
+    /// Constructs an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     /// Updates the orbit index every tick.
-    pub fn update(&mut self) {
-        todo!()
+    ///
+    /// `t` is the current time, which must not decrease between calls.
+    pub fn update(
+        &mut self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        t: units::GameInstant,
+    ) {
+        self.accel_sorted = self
+            .accelerating
+            .iter()
+            .filter_map(|&entity| Some(AccelEntry::new(entity, store_body.get(entity)?, t)))
+            .collect();
+        self.accel_sorted
+            .sort_by(|a, b| a.radius.total_cmp(&b.radius));
+
+        let count = self.radial.len() as f64;
+        if !self.resorting {
+            let limit = (count * RESORT_FRACTION).max(RESORT_MIN_CHECKS as f64);
+            let mut checked = 0;
+            while let Some(Reverse(cert)) = self.queue.peek() {
+                if cert.due > t {
+                    break;
+                }
+                let Reverse(cert) = self.queue.pop().expect("just peeked");
+                if self.versions.get(&cert.key) != Some(&cert.version) {
+                    continue; // superseded by a later certificate
+                }
+                let _ = self.versions.remove(&cert.key);
+
+                match cert.key {
+                    Key::Radial(entity) => self.check_radial(entity, t),
+                    Key::Angular(entity) => self.check_angular(entity, t),
+                    Key::Front => self.check_front(t),
+                    Key::Back => self.check_back(t),
+                }
+
+                checked += 1;
+                if checked as f64 > limit {
+                    self.resorting = true;
+                    self.queue.clear();
+                    self.versions.clear();
+                    break;
+                }
+            }
+        }
+
+        if self.resorting {
+            let moved = self.resort(t);
+            if moved as f64 <= count * KINETIC_FRACTION {
+                self.resorting = false;
+                self.reschedule(t);
+            }
+        }
+    }
+
+    /// Notifies the index that the orbit of `entity` has changed at time `t`,
+    /// e.g. due to an impulse.
+    ///
+    /// The new orbit is read from `store_body`,
+    /// and the certificates involving the entity are rechecked in the next `update()`.
+    /// Entities that are no longer orbiting should be removed and inserted again instead.
+    pub fn invalidate(
+        &mut self,
+        entity: Entity,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        t: units::GameInstant,
+    ) {
+        if !self.orbits.contains_key(&entity) || self.removed.contains(&entity) {
+            return;
+        }
+        let orbit = orbit_of(store_body, entity).clone();
+        let time = f64::from(t.0);
+        let bearing = orbit.unwrapped_bearing_at(time);
+        let _ = self.orbits.insert(entity, orbit);
+
+        if let Some(&i) = self.radial_rank.get(&entity) {
+            self.schedule_radial_around(i, t);
+        }
+
+        if let Some(slot) = self.angular_slot.get(&entity).copied() {
+            let i = (slot.rank - self.angular_base) as usize;
+            // The new orbit counts revolutions from a different epoch,
+            // so choose the winding that keeps the key closest to its neighbour.
+            let neighbour = if i > 0 {
+                self.angular.get(i - 1)
+            } else {
+                self.angular.get(i + 1)
+            };
+            let reference = match neighbour {
+                Some(&neighbour) => self.angular_key(neighbour, time),
+                None => PI,
+            };
+            self.slot_mut(entity).winding = ((bearing - reference) / (PI * 2.)).round() as i64;
+            self.schedule_angular_around(i, t);
+        }
+    }
+
+    /// Computes the extent of every entity with bounding boxes in `store_bb`,
+    /// i.e. the radius of the circle around the entity enclosing all its bounding boxes.
+    ///
+    /// This scans all bounding boxes once,
+    /// so it should be called once for all `insert()`s in a tick.
+    pub fn extents<D>(store_bb: &Storage<'_, BoundingBox, D>) -> HashMap<Entity, units::Length>
+    where
+        D: Deref<Target = MaskedStorage<BoundingBox>>,
+    {
+        let mut extents = HashMap::new();
+        for bb in store_bb.join() {
+            let extent = extents.entry(bb.parent()).or_insert(0.);
+            *extent = f64::max(*extent, bb.offset().0.norm() + bb.shape().radius());
+        }
+        extents
     }
 
-    /// Inserts an entity into the index.
-    pub fn insert(&mut self, entity: Entity, store_bb: WriteStorage<'_, BoundingBox>) {
-        todo!()
+    /// Inserts an entity into the index at time `t`.
+    ///
+    /// The extent of the entity is looked up from `extents` (see `BodyIndex::extents`),
+    /// and is zero if absent.
+    /// The entity should be inserted again if its bounding boxes change.
+    ///
+    /// Panics if the entity is a root star.
+    pub fn insert(
+        &mut self,
+        entity: Entity,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        extents: &HashMap<Entity, units::Length>,
+        t: units::GameInstant,
+    ) {
+        let body = store_body
+            .get(entity)
+            .expect("Indexed entity without a Body component");
+        if let Body::Root(_) = body {
+            unreachable!("A root star cannot be indexed");
+        }
+        if self.extents.contains_key(&entity) {
+            self.remove(entity, t);
+        }
+        if self.removed.contains(&entity) {
+            self.purge(entity, t);
+        }
+
+        let extent = extents.get(&entity).copied().unwrap_or(0.);
+        let _ = self.extents.insert(entity, extent);
+        *self.extent_counts.entry(extent.to_bits()).or_default() += 1;
+
+        let orbit = match body {
+            Body::Root(_) => unreachable!("A root star cannot be indexed"),
+            Body::Orbiting(body) => body.orbit().clone(),
+            Body::Accelerating(_) => {
+                let _ = self.accelerating.insert(entity);
+                let entry = AccelEntry::new(entity, body, t);
+                self.accel_sorted.retain(|other| other.entity != entity);
+                let i = self
+                    .accel_sorted
+                    .partition_point(|other| other.radius <= entry.radius);
+                self.accel_sorted.insert(i, entry);
+                return;
+            }
+            Body::Standing(_) => {
+                let _ = self.standing.insert(entity);
+                return;
+            }
+        };
+        let time = f64::from(t.0);
+
+        let i = match self.radial.binary_search_by(|&probe| {
+            self.orbit(probe)
+                .compare_radius(&orbit, t, 0.)
+                .then(Ordering::Less)
+        }) {
+            Ok(i) | Err(i) => i,
+        };
+        self.radial.insert(i, entity);
+        self.rerank_radial(i);
+        self.schedule_radial_around(i, t);
+
+        let bearing = orbit.unwrapped_bearing_at(time);
+        let winding = (bearing / (PI * 2.)).floor() as i64;
+        let key = bearing - winding as f64 * PI * 2.;
+        let i = match self.angular.binary_search_by(|&probe| {
+            self.angular_key(probe, time)
+                .total_cmp(&key)
+                .then(Ordering::Less)
+        }) {
+            Ok(i) | Err(i) => i,
+        };
+        self.angular.insert(i, entity);
+        let _ = self
+            .angular_slot
+            .insert(entity, AngularSlot { rank: 0, winding });
+        let _ = self.orbits.insert(entity, orbit);
+        self.rerank_angular(i);
+        self.schedule_angular_around(i, t);
     }
 
-    /// Removes an entity from the index.
-    pub fn remove(&mut self, entity: Entity) {
-        todo!()
+    /// Removes an entity from the index at time `t`.
+    ///
+    /// This is O(log n) amortized, and does not access the entity,
+    /// so it may be called after the entity is despawned or has changed.
+    /// Entities not in the index are ignored.
+    pub fn remove(&mut self, entity: Entity, t: units::GameInstant) {
+        let extent = match self.extents.remove(&entity) {
+            Some(extent) => extent,
+            None => return,
+        };
+        if let Some(count) = self.extent_counts.get_mut(&extent.to_bits()) {
+            *count -= 1;
+            if *count == 0 {
+                let _ = self.extent_counts.remove(&extent.to_bits());
+            }
+        }
+
+        if self.orbits.contains_key(&entity) {
+            let _ = self.removed.insert(entity);
+            if self.removed.len() * 2 > self.radial.len() {
+                self.compact(t);
+            }
+        }
+        let _ = self.accelerating.remove(&entity);
+        let _ = self.standing.remove(&entity);
     }
 
     /// Returns all entities in this index.
-    pub fn all(&self) -> impl Iterator<Item = Entity> {
-        std::iter::once(todo!())
+    pub fn all(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.all_orbiting()
+            .chain(self.all_accelerating())
+            .chain(self.all_standing())
     }
 
     /// Returns all entities in this index that are orbiting.
-    pub fn all_orbiting(&self) -> impl Iterator<Item = Entity> {
-        std::iter::once(todo!())
+    ///
+    /// The entities are sorted by radius as of the last update.
+    pub fn all_orbiting(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.radial
+            .iter()
+            .copied()
+            .filter(move |entity| !self.removed.contains(entity))
     }
 
     /// Returns all entities in this index that are accelerating.
-    pub fn all_accelerating(&self) -> impl Iterator<Item = Entity> {
-        std::iter::once(todo!())
+    pub fn all_accelerating(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.accelerating.iter().copied()
     }
 
     /// Returns all entities in this index that are standing.
-    pub fn all_standing(&self) -> impl Iterator<Item = Entity> {
-        std::iter::once(todo!())
+    pub fn all_standing(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.standing.iter().copied()
     }
 
-    /// Returns all entities in this index within the bearing.
+    /// Returns all entities in this index overlapping the bearing at time `t`,
+    /// i.e. the counterclockwise arc from `from` to `to` (both inclusive).
+    ///
+    /// Like all queries below, this uses the orders as of the last `update()`,
+    /// which should have been called with the same `t`,
+    /// and returns the entities whose extent overlaps the queried range.
+    /// The queries take `store_body` and `t` to compute the current positions of the candidates,
+    /// so callers of the earlier `between(from, to)` and `below(radius)` need to pass them too.
     pub fn between(
         &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
         from: units::Bearing,
         to: units::Bearing,
+        t: units::GameInstant,
+    ) -> impl Iterator<Item = Entity> {
+        let region = Region {
+            min_radius: 0.,
+            max_radius: f64::INFINITY,
+            arc: Arc::new(from, to),
+        };
+        self.region_where(store_body, region, t).into_iter()
+    }
+
+    /// Returns all entities in this index extending strictly below the radius at time `t`.
+    pub fn below(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        radius: units::Length,
+        t: units::GameInstant,
+    ) -> impl Iterator<Item = Entity> {
+        let high = radius + self.max_extent();
+        self.radius_where(store_body, 0., high, t, |center, extent| {
+            center - extent < radius
+        })
+        .into_iter()
+    }
+
+    /// Returns all entities in this index extending to or above the radius at time `t`.
+    pub fn above(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        radius: units::Length,
+        t: units::GameInstant,
     ) -> impl Iterator<Item = Entity> {
-        std::iter::once(todo!())
+        let low = radius - self.max_extent();
+        self.radius_where(store_body, low, f64::INFINITY, t, |center, extent| {
+            center + extent >= radius
+        })
+        .into_iter()
+    }
+
+    /// Returns all entities in this index overlapping the annular sector at time `t`,
+    /// i.e. with radius in `[min_radius, max_radius]`
+    /// and bearing in the counterclockwise arc from `from` to `to`.
+    ///
+    /// Only the smaller of the candidate sets from the radial and bearing orders is scanned.
+    /// Entities just outside the corners of the sector may also be returned.
+    pub fn sector(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        min_radius: units::Length,
+        max_radius: units::Length,
+        from: units::Bearing,
+        to: units::Bearing,
+        t: units::GameInstant,
+    ) -> Vec<Entity> {
+        let region = Region {
+            min_radius,
+            max_radius,
+            arc: Arc::new(from, to),
+        };
+        self.region_where(store_body, region, t)
+    }
+
+    /// Returns all entities in this index whose extent comes within `radius` of `position`
+    /// at time `t`.
+    pub fn near(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        position: units::Position,
+        radius: units::Length,
+        t: units::GameInstant,
+    ) -> Vec<Entity> {
+        let distance = position.0.norm();
+        let arc = if distance <= radius {
+            Arc::WHOLE
+        } else {
+            Arc::with_length(normal_bearing(position), 0.).widen((radius / distance).asin())
+        };
+        let region = Region {
+            min_radius: distance - radius,
+            max_radius: distance + radius,
+            arc,
+        };
+        self.region_where(store_body, region, t)
+            .into_iter()
+            .filter(|&other| {
+                let reach = radius + self.extent(other);
+                (position_of(store_body, other, t) - position).0.norm() <= reach
+            })
+            .collect()
+    }
+
+    /// Returns the pairs of entities in this index that may come into contact,
+    /// i.e. whose extents overlap at time `t` after being enlarged by `margin(entity)`.
+    ///
+    /// Each pair is returned once, with the lesser entity first.
+    /// Each pair is looked up from the entity with the larger enlarged extent,
+    /// so a few entities with large margins (e.g. fast bodies) do not slow down the others.
+    pub fn pairs(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        t: units::GameInstant,
+        margin: impl Fn(Entity) -> units::Length,
+    ) -> Vec<(Entity, Entity)> {
+        let reach = |entity| self.extent(entity) + margin(entity);
+        let mut pairs = vec![];
+        for entity in self.all() {
+            let position = position_of(store_body, entity, t);
+            let entity_reach = reach(entity);
+
+            // The other entity has no larger reach, so its centre is within twice this reach.
+            let distance = position.0.norm();
+            let radius = entity_reach * 2.;
+            let arc = if distance <= radius {
+                Arc::WHOLE
+            } else {
+                Arc::with_length(normal_bearing(position), 0.).widen((radius / distance).asin())
+            };
+            let region = Region {
+                min_radius: distance - radius,
+                max_radius: distance + radius,
+                arc,
+            };
+            for other in self.region_within(store_body, region, |_| 0., 0., t) {
+                let other_reach = reach(other);
+                if (other_reach, other) >= (entity_reach, entity) {
+                    continue;
+                }
+                let offset = position_of(store_body, other, t) - position;
+                if offset.0.norm() <= entity_reach + other_reach {
+                    pairs.push((entity.min(other), entity.max(other)));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Returns up to `k` entities in this index nearest to `position` at time `t`,
+    /// nearest first.
+    ///
+    /// The distance to an entity is measured to the boundary of its extent,
+    /// or zero if `position` is within its extent.
+    /// Orbiting and accelerating bodies are scanned outwards from the radius of `position`
+    /// in their radial orders,
+    /// stopping when the difference in radius alone exceeds the `k`-th nearest distance.
+    pub fn nearest(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        position: units::Position,
+        k: usize,
+        t: units::GameInstant,
+    ) -> Vec<Entity> {
+        if k == 0 {
+            return vec![];
+        }
+        let time = f64::from(t.0);
+        let max_extent = self.max_extent();
+
+        // the nearest entities found so far, sorted by distance
+        let mut nearest: Vec<(units::Length, Entity)> = Vec::with_capacity(k + 1);
+        let distance = |entity: Entity, other: units::Position| {
+            ((other - position).0.norm() - self.extent(entity)).max(0.)
+        };
+
+        for &entity in &self.standing {
+            let other = position_of(store_body, entity, t);
+            offer_nearest(&mut nearest, k, distance(entity, other), entity);
+        }
+
+        // Stop sweeping once every remaining body differs in radius by at least
+        // the distance to the `k`-th nearest entity plus the largest extent.
+        let radius = position.0.norm();
+        sweep_outward(
+            self.accel_sorted.len(),
+            self.accel_lower(radius),
+            radius,
+            |i| self.accel_sorted[i].radius,
+            |gap, i| {
+                if nearest.len() == k && gap - max_extent >= nearest[k - 1].0 {
+                    return false;
+                }
+                let entry = self.accel_sorted[i];
+                if self.accelerating.contains(&entry.entity) {
+                    let distance = distance(entry.entity, entry.position);
+                    offer_nearest(&mut nearest, k, distance, entry.entity);
+                }
+                true
+            },
+        );
+        sweep_outward(
+            self.radial.len(),
+            self.radial_lower(radius, time),
+            radius,
+            |i| self.orbit(self.radial[i]).radius_at(time, 0.),
+            |gap, i| {
+                if nearest.len() == k && gap - max_extent >= nearest[k - 1].0 {
+                    return false;
+                }
+                let entity = self.radial[i];
+                if !self.removed.contains(&entity) {
+                    let other = self.orbit(entity).position_at(time, 0.);
+                    offer_nearest(&mut nearest, k, distance(entity, other), entity);
+                }
+                true
+            },
+        );
+
+        nearest.into_iter().map(|(_, entity)| entity).collect()
+    }
+
+    /// Returns the first entity in this index hit by a ray at time `t`.
+    ///
+    /// The ray starts at `origin` in the direction `direction`, extends for `range`,
+    /// and hits the entities whose extent comes within `width` of its path.
+    /// Candidates are taken from the annular sector enclosing the path.
+    pub fn ray_cast(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        origin: units::Position,
+        direction: units::Bearing,
+        range: units::Length,
+        width: units::Length,
+        t: units::GameInstant,
+    ) -> Option<Entity> {
+        let unit = direction.to_unit_vector().0;
+        let end = origin + units::Displace(unit * range);
+
+        // the closest point of the path to the parent star
+        let closest = (-origin.0.dot(&unit)).max(0.).min(range);
+        let min_distance = (origin.0 + unit * closest).norm();
+
+        let arc = if min_distance <= width {
+            Arc::WHOLE
+        } else {
+            // The path does not pass the star, so it subtends less than half a revolution,
+            // and points within `width` of it are at most `asin(width / min_distance)` away.
+            let (a, b) = (normal_bearing(origin), normal_bearing(end));
+            let (start, length) = if (b - a).rem_euclid(PI * 2.) <= PI {
+                (a, (b - a).rem_euclid(PI * 2.))
+            } else {
+                (b, (a - b).rem_euclid(PI * 2.))
+            };
+            Arc::with_length(start, length).widen((width / min_distance).asin())
+        };
+        let region = Region {
+            min_radius: (min_distance - width).max(0.),
+            max_radius: origin.0.norm().max(end.0.norm()) + width,
+            arc,
+        };
+
+        self.region_where(store_body, region, t)
+            .into_iter()
+            .filter_map(|entity| {
+                let reach = width + self.extent(entity);
+                let offset = (position_of(store_body, entity, t) - origin).0;
+                let along = offset.dot(&unit);
+                let across_sq = offset.norm_squared() - along * along;
+                let half_chord = (reach * reach - across_sq).max(0.).sqrt();
+                if across_sq > reach * reach
+                    || along + half_chord < 0.
+                    || along - half_chord > range
+                {
+                    return None;
+                }
+                Some(((along - half_chord).max(0.), along, entity))
+            })
+            // entities already touching the origin are ordered by their projection on the ray
+            .min_by(|(a, a_along, _), (b, b_along, _)| {
+                a.total_cmp(b).then(a_along.total_cmp(b_along))
+            })
+            .map(|(_, _, entity)| entity)
+    }
+
+    /// Checks that both orders are sorted at time `t`, panicking otherwise.
+    ///
+    /// This is O(n) and is only intended for debugging.
+    #[cfg(debug_assertions)]
+    pub fn check_invariants(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        t: units::GameInstant,
+    ) {
+        for (i, pair) in self.radial.windows(2).enumerate() {
+            let order = self
+                .orbit(pair[0])
+                .compare_radius(self.orbit(pair[1]), t, 0.);
+            assert_ne!(order, Ordering::Greater, "radial order broken at {}", i);
+        }
+        for (i, &entity) in self.radial.iter().enumerate() {
+            assert_eq!(
+                self.radial_rank.get(&entity),
+                Some(&i),
+                "radial rank broken"
+            );
+        }
+
+        let time = f64::from(t.0);
+        let keys: Vec<f64> = self
+            .angular
+            .iter()
+            .map(|&entity| self.angular_key(entity, time))
+            .collect();
+        for (i, pair) in keys.windows(2).enumerate() {
+            assert!(pair[0] <= pair[1], "angular order broken at {}", i);
+        }
+        if let (Some(&first), Some(&last)) = (keys.first(), keys.last()) {
+            assert!(first >= 0. && last < PI * 2., "angular wrap broken");
+        }
+        for (i, &entity) in self.angular.iter().enumerate() {
+            let rank = self.angular_slot.get(&entity).map(|slot| slot.rank);
+            assert_eq!(
+                rank,
+                Some(self.angular_base + i as i64),
+                "angular rank broken"
+            );
+        }
+
+        for entity in self.all_orbiting() {
+            match store_body.get(entity) {
+                Some(Body::Orbiting(body)) => assert!(
+                    body.orbit().radius_at(time, 0.) == self.orbit(entity).radius_at(time, 0.),
+                    "orbit changed without invalidation"
+                ),
+                _ => panic!("Indexed orbiting entity is no longer orbiting"),
+            }
+        }
+        assert_eq!(
+            self.extent_counts.values().sum::<usize>(),
+            self.extents.len(),
+            "extent counts broken"
+        );
+    }
+
+    /// Checks the radial certificate of `entity` and its successor.
+    fn check_radial(&mut self, entity: Entity, t: units::GameInstant) {
+        let i = match self.radial_rank.get(&entity) {
+            Some(&i) => i,
+            None => return,
+        };
+        let next = match self.radial.get(i + 1) {
+            Some(&next) => next,
+            None => return,
+        };
+
+        let orbit = self.orbit(entity);
+        let next_orbit = self.orbit(next);
+        if orbit.compare_radius(next_orbit, t, 0.) == Ordering::Greater {
+            self.radial.swap(i, i + 1);
+            let _ = self.radial_rank.insert(entity, i + 1);
+            let _ = self.radial_rank.insert(next, i);
+            self.schedule_radial_around(i, t);
+            self.schedule_radial_around(i + 1, t);
+        } else {
+            // Neither orbit can pass the other before one of them crosses the middle radius.
+            let time = f64::from(t.0);
+            let middle = (orbit.radius_at(time, 0.) + next_orbit.radius_at(time, 0.)) / 2.;
+            let due = orbit
+                .when_intersect_radius(middle, t)
+                .into_iter()
+                .chain(next_orbit.when_intersect_radius(middle, t))
+                .min();
+            self.schedule(Key::Radial(entity), due.map(|due| later(due, t)));
+        }
+    }
+
+    /// Checks the angular certificate of `entity` and its successor.
+    fn check_angular(&mut self, entity: Entity, t: units::GameInstant) {
+        let i = match self.angular_slot.get(&entity) {
+            Some(slot) => (slot.rank - self.angular_base) as usize,
+            None => return,
+        };
+        let next = match self.angular.get(i + 1) {
+            Some(&next) => next,
+            None => return,
+        };
+
+        let time = f64::from(t.0);
+        if self.angular_key(entity, time) > self.angular_key(next, time) {
+            self.angular.swap(i, i + 1);
+            self.slot_mut(entity).rank += 1;
+            self.slot_mut(next).rank -= 1;
+            self.schedule_angular_around(i, t);
+            self.schedule_angular_around(i + 1, t);
+        } else {
+            let due =
+                self.orbit(entity)
+                    .when_intersect_bearing(self.orbit(next), units::Theta(0.), t);
+            self.schedule(Key::Angular(entity), due.map(|due| later(due, t)));
+        }
+    }
+
+    /// Checks whether the first entity in `angular` has crossed the zero bearing clockwise,
+    /// moving it to the end if so.
+    fn check_front(&mut self, t: units::GameInstant) {
+        let front = match self.angular.front() {
+            Some(&front) => front,
+            None => return,
+        };
+
+        if self.angular_key(front, f64::from(t.0)) < 0. {
+            let _ = self.angular.pop_front();
+            self.angular_base += 1;
+            if let Some(&back) = self.angular.back() {
+                self.schedule(Key::Angular(back), Some(t));
+            }
+            self.angular.push_back(front);
+            let rank = self.angular_base + self.angular.len() as i64 - 1;
+            let slot = self.slot_mut(front);
+            slot.rank = rank;
+            slot.winding -= 1;
+            let _ = self.versions.remove(&Key::Angular(front));
+            self.schedule(Key::Front, Some(t));
+            self.schedule(Key::Back, Some(t));
+        } else {
+            let due = self.orbit(front).when_cross_bearing(units::Bearing(0.), t);
+            self.schedule(Key::Front, due.map(|due| later(due, t)));
+        }
+    }
+
+    /// Checks whether the last entity in `angular` has crossed the zero bearing counterclockwise,
+    /// moving it to the start if so.
+    fn check_back(&mut self, t: units::GameInstant) {
+        let back = match self.angular.back() {
+            Some(&back) => back,
+            None => return,
+        };
+
+        if self.angular_key(back, f64::from(t.0)) >= PI * 2. {
+            let _ = self.angular.pop_back();
+            if let Some(&last) = self.angular.back() {
+                let _ = self.versions.remove(&Key::Angular(last));
+            }
+            self.angular_base -= 1;
+            self.angular.push_front(back);
+            let rank = self.angular_base;
+            let slot = self.slot_mut(back);
+            slot.rank = rank;
+            slot.winding += 1;
+            if self.angular.len() > 1 {
+                self.schedule(Key::Angular(back), Some(t));
+            }
+            self.schedule(Key::Front, Some(t));
+            self.schedule(Key::Back, Some(t));
+        } else {
+            let due = self.orbit(back).when_cross_bearing(units::Bearing(0.), t);
+            self.schedule(Key::Back, due.map(|due| later(due, t)));
+        }
+    }
+
+    /// Sorts `radial` and `angular` from scratch at time `t`,
+    /// returning the number of entities moved in either order.
+    ///
+    /// The winding of each entity is reset so that all sort keys are in `[0, 2pi)`.
+    fn resort(&mut self, t: units::GameInstant) -> usize {
+        let time = f64::from(t.0);
+
+        let mut radial: Vec<(units::Length, Entity)> = self
+            .radial
+            .iter()
+            .map(|&entity| (self.orbit(entity).radius_at(time, 0.), entity))
+            .collect();
+        radial.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut moved: HashSet<Entity> = HashSet::new();
+        for (i, &(_, entity)) in radial.iter().enumerate() {
+            if self.radial[i] != entity {
+                let _ = moved.insert(entity);
+                self.radial[i] = entity;
+                let _ = self.radial_rank.insert(entity, i);
+            }
+        }
+
+        let mut angular: Vec<(f64, i64, Entity)> = self
+            .angular
+            .iter()
+            .map(|&entity| {
+                let bearing = self.orbit(entity).unwrapped_bearing_at(time);
+                let mut winding = (bearing / (PI * 2.)).floor() as i64;
+                // rounding may leave the key just outside `[0, 2pi)`
+                let key = bearing - winding as f64 * PI * 2.;
+                if key >= PI * 2. {
+                    winding += 1;
+                } else if key < 0. {
+                    winding -= 1;
+                }
+                (bearing - winding as f64 * PI * 2., winding, entity)
+            })
+            .collect();
+        angular.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (i, &(_, winding, entity)) in angular.iter().enumerate() {
+            if self.angular[i] != entity {
+                let _ = moved.insert(entity);
+                self.angular[i] = entity;
+            }
+            let rank = self.angular_base + i as i64;
+            let _ = self
+                .angular_slot
+                .insert(entity, AngularSlot { rank, winding });
+        }
+
+        moved.len()
     }
 
-    /// Returns all entities in this index below the radius.
-    pub fn below(&self, radius: units::Length) -> impl Iterator<Item = Entity> {
-        std::iter::once(todo!())
+    /// Replaces all certificates with ones computed at time `t`,
+    /// when both orders are sorted.
+    fn reschedule(&mut self, t: units::GameInstant) {
+        self.queue.clear();
+        self.versions.clear();
+        for i in 1..self.radial.len() {
+            self.check_radial(self.radial[i - 1], t);
+        }
+        for i in 1..self.angular.len() {
+            self.check_angular(self.angular[i - 1], t);
+        }
+        self.check_front(t);
+        self.check_back(t);
     }
 
-    /// Returns all entities in this index above the radius.
-    pub fn above(&self, radius: units::Length) -> impl Iterator<Item = Entity> {
-        std::iter::once(todo!())
+    /// Drops all tombstones from `radial` and `angular` at time `t`.
+    ///
+    /// The remaining entities stay in order,
+    /// but all certificates are rechecked since their successors may have changed.
+    fn compact(&mut self, t: units::GameInstant) {
+        let removed = std::mem::take(&mut self.removed);
+        for entity in &removed {
+            let _ = self.orbits.remove(entity);
+            let _ = self.radial_rank.remove(entity);
+            let _ = self.angular_slot.remove(entity);
+            let _ = self.versions.remove(&Key::Radial(*entity));
+            let _ = self.versions.remove(&Key::Angular(*entity));
+        }
+        self.radial.retain(|entity| !removed.contains(entity));
+        self.angular.retain(|entity| !removed.contains(entity));
+        self.rerank_radial(0);
+        self.rerank_angular(0);
+
+        for i in 1..self.radial.len() {
+            self.schedule(Key::Radial(self.radial[i - 1]), Some(t));
+        }
+        for i in 1..self.angular.len() {
+            self.schedule(Key::Angular(self.angular[i - 1]), Some(t));
+        }
+        self.schedule(Key::Front, Some(t));
+        self.schedule(Key::Back, Some(t));
+    }
+
+    /// Drops the tombstone of a single entity from `radial` and `angular` at time `t`.
+    ///
+    /// This is O(n), and is only used when a removed entity is inserted again.
+    fn purge(&mut self, entity: Entity, t: units::GameInstant) {
+        let _ = self.removed.remove(&entity);
+        let _ = self.orbits.remove(&entity);
+
+        if let Some(i) = self.radial_rank.remove(&entity) {
+            let _ = self.radial.remove(i);
+            let _ = self.versions.remove(&Key::Radial(entity));
+            self.rerank_radial(i);
+            if i > 0 {
+                self.schedule(Key::Radial(self.radial[i - 1]), Some(t));
+            }
+        }
+
+        if let Some(slot) = self.angular_slot.remove(&entity) {
+            let i = (slot.rank - self.angular_base) as usize;
+            let _ = self.angular.remove(i);
+            let _ = self.versions.remove(&Key::Angular(entity));
+            self.rerank_angular(i);
+            if i > 0 {
+                self.schedule(Key::Angular(self.angular[i - 1]), Some(t));
+            }
+            self.schedule(Key::Front, Some(t));
+            self.schedule(Key::Back, Some(t));
+        }
+    }
+
+    /// Collects the entities overlapping a region at time `t`.
+    fn region_where(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        region: Region,
+        t: units::GameInstant,
+    ) -> Vec<Entity> {
+        self.region_within(
+            store_body,
+            region,
+            |entity| self.extent(entity),
+            self.max_extent(),
+            t,
+        )
+    }
+
+    /// Collects the entities that come within `extent(entity)` of a region at time `t`,
+    /// where `max_extent` is the largest value of `extent`.
+    fn region_within(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        region: Region,
+        extent: impl Fn(Entity) -> units::Length,
+        max_extent: units::Length,
+        t: units::GameInstant,
+    ) -> Vec<Entity> {
+        let time = f64::from(t.0);
+        let low = region.min_radius - max_extent;
+        let high = region.max_radius + max_extent;
+        let overlaps = |entity: Entity, position| region.overlaps(position, extent(entity));
+        let orbiting = |&entity: &Entity| {
+            !self.removed.contains(&entity)
+                && overlaps(entity, self.orbit(entity).position_at(time, 0.))
+        };
+
+        // Bodies beyond `cut` are at most `margin` away from the arc in bearing.
+        let (cut, margin) = if max_extent > 0. {
+            (max_extent * CUT_RATIO, (1. / CUT_RATIO).asin())
+        } else {
+            (0., 0.)
+        };
+        let radial = self.radial_lower(low, time)..self.radial_upper(high, time);
+        let (first, second) = self.angular_ranges(region.arc.widen(margin), time);
+
+        let mut entities: Vec<Entity> = if radial.len() <= first.len() + second.len() {
+            self.radial[radial]
+                .iter()
+                .copied()
+                .filter(orbiting)
+                .collect()
+        } else {
+            let inner = radial.start
+                ..self
+                    .radial_lower(cut, time)
+                    .max(radial.start)
+                    .min(radial.end);
+            let outer = first
+                .chain(second)
+                .map(|i| self.angular[i])
+                .filter(|&entity| self.orbit(entity).radius_at(time, 0.) >= cut);
+            self.radial[inner]
+                .iter()
+                .copied()
+                .chain(outer)
+                .filter(orbiting)
+                .collect()
+        };
+
+        let accel = self.accel_lower(low)..self.accel_upper(high);
+        entities.extend(
+            self.accel_sorted[accel]
+                .iter()
+                .filter(|entry| {
+                    self.accelerating.contains(&entry.entity)
+                        && overlaps(entry.entity, entry.position)
+                })
+                .map(|entry| entry.entity),
+        );
+        entities.extend(
+            self.standing
+                .iter()
+                .copied()
+                .filter(|&entity| overlaps(entity, position_of(store_body, entity, t))),
+        );
+        entities
+    }
+
+    /// Collects the entities with radius in `[low, high)` at time `t`
+    /// for which `predicate` accepts the radius and the extent.
+    fn radius_where(
+        &self,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        low: units::Length,
+        high: units::Length,
+        t: units::GameInstant,
+        predicate: impl Fn(units::Length, units::Length) -> bool,
+    ) -> Vec<Entity> {
+        let time = f64::from(t.0);
+        let radial = self.radial_lower(low, time)..self.radial_lower(high, time);
+        let accel = self.accel_lower(low)..self.accel_lower(high);
+
+        let orbiting = self.radial[radial]
+            .iter()
+            .copied()
+            .filter(|entity| !self.removed.contains(entity))
+            .filter(|&entity| {
+                predicate(self.orbit(entity).radius_at(time, 0.), self.extent(entity))
+            });
+        let accelerating = self.accel_sorted[accel]
+            .iter()
+            .filter(|entry| {
+                self.accelerating.contains(&entry.entity)
+                    && predicate(entry.radius, self.extent(entry.entity))
+            })
+            .map(|entry| entry.entity);
+        let standing = self.standing.iter().copied().filter(|&entity| {
+            predicate(
+                position_of(store_body, entity, t).0.norm(),
+                self.extent(entity),
+            )
+        });
+        orbiting.chain(accelerating).chain(standing).collect()
+    }
+
+    /// Returns the position of the first entry in `accel_sorted` with radius not below `radius`.
+    fn accel_lower(&self, radius: units::Length) -> usize {
+        self.accel_sorted
+            .partition_point(|entry| entry.radius < radius)
+    }
+
+    /// Returns the position of the first entry in `accel_sorted` with radius above `radius`.
+    fn accel_upper(&self, radius: units::Length) -> usize {
+        self.accel_sorted
+            .partition_point(|entry| entry.radius <= radius)
+    }
+
+    /// Returns the position of the first entity in `radial` with radius not below `radius`.
+    fn radial_lower(&self, radius: units::Length, time: f64) -> usize {
+        self.radial
+            .partition_point(|&entity| self.orbit(entity).radius_at(time, 0.) < radius)
+    }
+
+    /// Returns the position of the first entity in `radial` with radius above `radius`.
+    fn radial_upper(&self, radius: units::Length, time: f64) -> usize {
+        self.radial
+            .partition_point(|&entity| self.orbit(entity).radius_at(time, 0.) <= radius)
+    }
+
+    /// Returns the ranges of positions in `angular` covered by `arc`.
+    ///
+    /// The arc may wrap around the zero bearing, in which case it is split into two ranges.
+    fn angular_ranges(&self, arc: Arc, time: f64) -> (Range<usize>, Range<usize>) {
+        if arc.length >= PI * 2. {
+            return (0..self.angular.len(), 0..0);
+        }
+        let lower = |key: f64| {
+            self.angular
+                .partition_point(|&entity| self.angular_key(entity, time) < key)
+        };
+        let upper = |key: f64| {
+            self.angular
+                .partition_point(|&entity| self.angular_key(entity, time) <= key)
+        };
+        let end = arc.start + arc.length;
+        if end < PI * 2. {
+            (lower(arc.start)..upper(end), 0..0)
+        } else {
+            (
+                lower(arc.start)..self.angular.len(),
+                0..upper(end - PI * 2.),
+            )
+        }
+    }
+
+    /// Retrieves the orbit of an orbiting entity in `radial` and `angular`.
+    fn orbit(&self, entity: Entity) -> &Orbit {
+        self.orbits
+            .get(&entity)
+            .expect("Entity in orbiting orders without an orbit")
+    }
+
+    /// Retrieves the extent of an indexed entity.
+    fn extent(&self, entity: Entity) -> units::Length {
+        self.extents.get(&entity).copied().unwrap_or_default()
+    }
+
+    /// Returns the largest extent among the indexed entities.
+    fn max_extent(&self) -> units::Length {
+        self.extent_counts
+            .keys()
+            .next_back()
+            .map_or(0., |&bits| f64::from_bits(bits))
+    }
+
+    /// Computes the sort key of an entity in `angular` at `time`.
+    fn angular_key(&self, entity: Entity, time: f64) -> f64 {
+        let winding = self
+            .angular_slot
+            .get(&entity)
+            .map_or(0, |slot| slot.winding);
+        self.orbit(entity).unwrapped_bearing_at(time) - winding as f64 * PI * 2.
+    }
+
+    /// Retrieves the slot of an entity in `angular`.
+    fn slot_mut(&mut self, entity: Entity) -> &mut AngularSlot {
+        self.angular_slot
+            .get_mut(&entity)
+            .expect("Entity in angular order without a slot")
+    }
+
+    /// Schedules the radial certificates involving the entity at `i` to be checked at `t`.
+    fn schedule_radial_around(&mut self, i: usize, t: units::GameInstant) {
+        if i > 0 {
+            self.schedule(Key::Radial(self.radial[i - 1]), Some(t));
+        }
+        if i + 1 < self.radial.len() {
+            self.schedule(Key::Radial(self.radial[i]), Some(t));
+        }
+    }
+
+    /// Schedules the angular certificates involving the entity at `i` to be checked at `t`.
+    fn schedule_angular_around(&mut self, i: usize, t: units::GameInstant) {
+        if i > 0 {
+            self.schedule(Key::Angular(self.angular[i - 1]), Some(t));
+        }
+        if i + 1 < self.angular.len() {
+            self.schedule(Key::Angular(self.angular[i]), Some(t));
+        }
+        if i == 0 {
+            self.schedule(Key::Front, Some(t));
+        }
+        if i + 1 >= self.angular.len() {
+            self.schedule(Key::Back, Some(t));
+        }
+    }
+
+    /// Schedules a certificate for `key` due at `due`, replacing any existing certificate.
+    ///
+    /// Nothing is scheduled while the orders are re-sorted in every update.
+    ///
+    /// If `due` is `None`, the ordering never changes and no certificate is scheduled.
+    fn schedule(&mut self, key: Key, due: Option<units::GameInstant>) {
+        if self.resorting {
+            return; // the orders are re-sorted in the next update anyway
+        }
+        match due {
+            Some(due) => {
+                let version = self.next_version;
+                self.next_version += 1;
+                let _ = self.versions.insert(key, version);
+                self.queue.push(Reverse(Certificate { due, version, key }));
+
+                // Superseded certificates are only dropped when they are due,
+                // so compact the queue if they dominate it.
+                if self.queue.len() > (self.versions.len() + 1) * COMPACT_RATIO {
+                    let versions = &self.versions;
+                    let queue = std::mem::take(&mut self.queue);
+                    self.queue = queue
+                        .into_iter()
+                        .filter(|Reverse(cert)| versions.get(&cert.key) == Some(&cert.version))
+                        .collect();
+                }
+            }
+            None => {
+                let _ = self.versions.remove(&key);
+            }
+        }
+    }
+
+    /// Updates the ranks of the entities in `radial` from position `from`.
+    fn rerank_radial(&mut self, from: usize) {
+        for (i, &entity) in self.radial.iter().enumerate().skip(from) {
+            let _ = self.radial_rank.insert(entity, i);
+        }
+    }
+
+    /// Updates the ranks of the entities in `angular` from position `from`.
+    fn rerank_angular(&mut self, from: usize) {
+        for i in from..self.angular.len() {
+            let rank = self.angular_base + i as i64;
+            let entity = self.angular[i];
+            self.slot_mut(entity).rank = rank;
+        }
+    }
+}
+
+/// Retrieves the orbit of an orbiting entity from the storage.
+fn orbit_of(store_body: &impl GenericReadStorage<Component = Body>, entity: Entity) -> &Orbit {
+    match store_body.get(entity) {
+        Some(Body::Orbiting(body)) => body.orbit(),
+        _ => panic!("Indexed orbiting entity is no longer orbiting"),
+    }
+}
+
+impl AccelEntry {
+    /// Records the position of an accelerating entity at time `t`.
+    fn new(entity: Entity, body: &Body, t: units::GameInstant) -> Self {
+        let position = body.position(t);
+        Self {
+            radius: position.0.norm(),
+            position,
+            entity,
+        }
+    }
+}
+
+/// Visits the positions `0..len` of a sequence sorted by `radius_of`,
+/// from `split` outwards in increasing difference of radius from `radius`.
+///
+/// `visit` is called with the difference and the position, and returns `false` to stop.
+fn sweep_outward(
+    len: usize,
+    split: usize,
+    radius: units::Length,
+    radius_of: impl Fn(usize) -> units::Length,
+    mut visit: impl FnMut(units::Length, usize) -> bool,
+) {
+    let (mut inner, mut outer) = (split, split);
+    loop {
+        let inner_gap = inner.checked_sub(1).map(|i| radius - radius_of(i));
+        let outer_gap = if outer < len {
+            Some(radius_of(outer) - radius)
+        } else {
+            None
+        };
+        let (gap, i) = match (inner_gap, outer_gap) {
+            (Some(inner_gap), Some(outer_gap)) if inner_gap <= outer_gap => {
+                inner -= 1;
+                (inner_gap, inner)
+            }
+            (_, Some(outer_gap)) => {
+                outer += 1;
+                (outer_gap, outer - 1)
+            }
+            (Some(inner_gap), None) => {
+                inner -= 1;
+                (inner_gap, inner)
+            }
+            (None, None) => return,
+        };
+        if !visit(gap, i) {
+            return;
+        }
+    }
+}
+
+/// Inserts an entity into `nearest`, which keeps the `k` nearest entities sorted by distance.
+fn offer_nearest(
+    nearest: &mut Vec<(units::Length, Entity)>,
+    k: usize,
+    distance: units::Length,
+    entity: Entity,
+) {
+    if nearest.len() == k && distance >= nearest[k - 1].0 {
+        return;
+    }
+    let i = nearest.partition_point(|&(other, _)| other <= distance);
+    nearest.insert(i, (distance, entity));
+    nearest.truncate(k);
+}
+
+/// Retrieves the position of an indexed entity at time `t`.
+fn position_of(
+    store_body: &impl GenericReadStorage<Component = Body>,
+    entity: Entity,
+    t: units::GameInstant,
+) -> units::Position {
+    store_body
+        .get(entity)
+        .expect("Indexed entity without a Body component")
+        .position(t)
+}
+
+/// Computes the bearing of a position from the parent star, normalized to `[0, 2pi)`.
+fn normal_bearing(position: units::Position) -> f64 {
+    normalize(position.0[1].atan2(position.0[0]))
+}
+
+/// Normalizes an angle to `[0, 2pi)`.
+fn normalize(angle: f64) -> f64 {
+    let angle = angle.rem_euclid(PI * 2.);
+    // `rem_euclid` rounds tiny negative values up to `2pi`
+    if angle < PI * 2. {
+        angle
+    } else {
+        0.
+    }
+}
+
+/// Ensures a rescheduled certificate is due strictly after the current tick,
+/// so that each certificate is checked at most once per tick.
+fn later(due: units::GameInstant, t: units::GameInstant) -> units::GameInstant {
+    due.max(t + units::GameDuration::UNIT)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use nalgebra::Vector2;
+    use specs::{Builder, World, WorldExt};
+
+    use super::*;
+    use crate::collision::bb::{Shape, Variant};
+    use crate::{AccelBody, OrbitalState, OrbitingBody, StandingBody};
+
+    const M: units::Mass = units::Mass(1000.);
+
+    /// A deterministic xorshift generator of values in `[0, 1)`.
+    fn random(seed: &mut u64) -> f64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        (*seed >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn world() -> (World, Entity) {
+        let mut world = World::new();
+        world.register::<Body>();
+        world.register::<BoundingBox>();
+        let star = world.create_entity().build();
+        (world, star)
+    }
+
+    /// Creates a body on a random orbit at a distance of 50 to 250 from the star at instant 0.
+    ///
+    /// One in five orbits is hyperbolic, one in four is clockwise,
+    /// and half of the bodies have one or two circular BBs.
+    fn spawn_orbiting(world: &mut World, star: Entity, seed: &mut u64) -> Entity {
+        let radius = 50. + random(seed) * 200.;
+        let bearing = random(seed) * PI * 2.;
+        let speed = (M.0 / radius).sqrt()
+            * if random(seed) < 0.2 {
+                1.5 + random(seed) * 0.5
+            } else {
+                0.7 + random(seed) * 0.6
+            };
+        let rotation = if random(seed) < 0.25 { -1. } else { 1. };
+        let heading = bearing + rotation * (1.2 + random(seed) * 0.7);
+        let state = OrbitalState::new(
+            units::Position(Vector2::new(radius * bearing.cos(), radius * bearing.sin())),
+            units::Velocity::of(units::Displace(Vector2::new(
+                speed * heading.cos(),
+                speed * heading.sin(),
+            ))),
+        );
+        let orbit = Orbit::from_states(state, units::GameInstant(0), M);
+        let entity = world
+            .create_entity()
+            .with(Body::Orbiting(OrbitingBody::new(orbit, star)))
+            .build();
+        spawn_bbs(world, entity, seed);
+        entity
+    }
+
+    /// Attaches zero to two circular BBs to `parent`.
+    fn spawn_bbs(world: &mut World, parent: Entity, seed: &mut u64) {
+        let count = (random(seed) * 4.) as usize;
+        for _ in 0..count.saturating_sub(1) {
+            let radius = 1. + random(seed) * 4.;
+            let offset = Vector2::new(random(seed) * 3., random(seed) * 3.);
+            let _ = world
+                .create_entity()
+                .with(BoundingBox::new(
+                    Shape::Circle { radius },
+                    Variant::Solid(units::Elasticity(1.)),
+                    parent,
+                    units::Displace(offset),
+                ))
+                .build();
+        }
+    }
+
+    /// Creates a body on a counterclockwise circular orbit at `position` at instant 0,
+    /// with a circular BB of `radius` at `offset` if `radius` is positive.
+    fn spawn_at(
+        world: &mut World,
+        star: Entity,
+        position: Vector2<f64>,
+        offset: Vector2<f64>,
+        radius: units::Length,
+    ) -> Entity {
+        let speed = (M.0 / position.norm()).sqrt() / position.norm();
+        let state = OrbitalState::new(
+            units::Position(position),
+            units::Velocity::of(units::Displace(Vector2::new(
+                -position[1] * speed,
+                position[0] * speed,
+            ))),
+        );
+        let orbit = Orbit::from_states(state, units::GameInstant(0), M);
+        let entity = world
+            .create_entity()
+            .with(Body::Orbiting(OrbitingBody::new(orbit, star)))
+            .build();
+        if radius > 0. {
+            let _ = world
+                .create_entity()
+                .with(BoundingBox::new(
+                    Shape::Circle { radius },
+                    Variant::Solid(units::Elasticity(1.)),
+                    entity,
+                    units::Displace(offset),
+                ))
+                .build();
+        }
+        entity
+    }
+
+    /// Computes the extent of `entity` by scanning all BBs.
+    fn extent_of(world: &World, entity: Entity) -> units::Length {
+        world
+            .read_storage::<BoundingBox>()
+            .join()
+            .filter(|bb| bb.parent() == entity)
+            .map(|bb| bb.offset().0.norm() + bb.shape().radius())
+            .fold(0., f64::max)
+    }
+
+    /// Scans `entities` for those whose position and extent at `t` satisfy `filter`.
+    fn brute_force(
+        world: &World,
+        entities: &BTreeSet<Entity>,
+        t: units::GameInstant,
+        filter: impl Fn(units::Position, units::Length) -> bool,
+    ) -> BTreeSet<Entity> {
+        let store_body = world.read_storage::<Body>();
+        entities
+            .iter()
+            .copied()
+            .filter(|&entity| {
+                let position = store_body.get(entity).unwrap().position(t);
+                filter(position, extent_of(world, entity))
+            })
+            .collect()
+    }
+
+    /// Checks whether a circle at `position` with radius `extent`
+    /// overlaps the counterclockwise arc of bearings from `from` to `to`.
+    fn overlaps_arc(position: units::Position, extent: units::Length, from: f64, to: f64) -> bool {
+        let radius = position.0.norm();
+        let bearing = position.0[1].atan2(position.0[0]);
+        let length = (to - from).rem_euclid(PI * 2.);
+        let offset = (bearing - from).rem_euclid(PI * 2.);
+        let distance = if offset <= length {
+            0.
+        } else {
+            (offset - length).min(PI * 2. - offset)
+        };
+        radius <= extent || distance <= (extent / radius).asin()
+    }
+
+    #[test]
+    fn kinetic_update() {
+        let (mut world, star) = world();
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        let pool: Vec<Entity> = (0..400)
+            .map(|_| spawn_orbiting(&mut world, star, &mut seed))
+            .collect();
+
+        let store_body = world.read_storage::<Body>();
+        let extents = BodyIndex::extents(&world.read_storage::<BoundingBox>());
+        let mut index = BodyIndex::new();
+        let mut indexed: BTreeSet<Entity> = pool[..300].iter().copied().collect();
+        for &entity in &indexed {
+            index.insert(entity, &store_body, &extents, units::GameInstant(0));
+        }
+
+        for tick in 0..3000 {
+            let t = units::GameInstant(tick);
+            if tick % 20 == 10 {
+                // replace a random indexed body with a random body from the pool,
+                // which may have been indexed before
+                let removed = *indexed
+                    .iter()
+                    .nth((random(&mut seed) * indexed.len() as f64) as usize)
+                    .unwrap();
+                index.remove(removed, t);
+                let _ = indexed.remove(&removed);
+                let inserted = pool[(random(&mut seed) * pool.len() as f64) as usize];
+                index.insert(inserted, &store_body, &extents, t);
+                let _ = indexed.insert(inserted);
+            }
+            index.update(&store_body, t);
+            #[cfg(debug_assertions)]
+            index.check_invariants(&store_body, t);
+            assert_eq!(index.all().collect::<BTreeSet<_>>(), indexed);
+
+            if tick % 50 == 0 {
+                let radius = 50. + random(&mut seed) * 200.;
+                let below: BTreeSet<Entity> = index.below(&store_body, radius, t).collect();
+                let expected = brute_force(&world, &indexed, t, |position, extent| {
+                    position.0.norm() - extent < radius
+                });
+                assert_eq!(below, expected, "below {} at {}", radius, tick);
+
+                let above: BTreeSet<Entity> = index.above(&store_body, radius, t).collect();
+                let expected = brute_force(&world, &indexed, t, |position, extent| {
+                    position.0.norm() + extent >= radius
+                });
+                assert_eq!(above, expected, "above {} at {}", radius, tick);
+
+                let from = random(&mut seed) * PI * 2.;
+                let to = from + random(&mut seed) * PI * 2.;
+                let between: BTreeSet<Entity> = index
+                    .between(&store_body, units::Bearing(from), units::Bearing(to), t)
+                    .collect();
+                let expected = brute_force(&world, &indexed, t, |position, extent| {
+                    overlaps_arc(position, extent, from, to)
+                });
+                assert_eq!(between, expected, "between {} and {} at {}", from, to, tick);
+            }
+        }
+    }
+
+    #[test]
+    fn extents() {
+        let (mut world, star) = world();
+        let a = spawn_at(
+            &mut world,
+            star,
+            Vector2::new(100., 0.),
+            Vector2::new(3., 4.),
+            2.,
+        );
+        let _ = world
+            .create_entity()
+            .with(BoundingBox::new(
+                Shape::Circle { radius: 1. },
+                Variant::Solid(units::Elasticity(1.)),
+                a,
+                units::Displace(Vector2::new(-1., 0.)),
+            ))
+            .build();
+        let b = spawn_at(
+            &mut world,
+            star,
+            Vector2::new(0., 100.),
+            Vector2::zeros(),
+            0.,
+        );
+
+        let extents = BodyIndex::extents(&world.read_storage::<BoundingBox>());
+        assert_eq!(extents.get(&a), Some(&7.));
+        assert_eq!(extents.get(&b), None);
+    }
+
+    #[test]
+    fn insert_remove_extent() {
+        let (mut world, star) = world();
+        // the BB reaches from radius 105 to 115, so the extent is 15
+        let a = spawn_at(
+            &mut world,
+            star,
+            Vector2::new(100., 0.),
+            Vector2::new(10., 0.),
+            5.,
+        );
+        let b = spawn_at(
+            &mut world,
+            star,
+            Vector2::new(150., 0.),
+            Vector2::zeros(),
+            0.,
+        );
+        let t = units::GameInstant(0);
+
+        let mut index = BodyIndex::new();
+        {
+            let store_body = world.read_storage::<Body>();
+            let extents = BodyIndex::extents(&world.read_storage::<BoundingBox>());
+            index.insert(a, &store_body, &extents, t);
+            index.insert(b, &store_body, &extents, t);
+            index.update(&store_body, t);
+
+            let below = |radius| index.below(&store_body, radius, t).collect::<BTreeSet<_>>();
+            let above = |radius| index.above(&store_body, radius, t).collect::<BTreeSet<_>>();
+            let between = |from, to| {
+                index
+                    .between(&store_body, units::Bearing(from), units::Bearing(to), t)
+                    .collect::<BTreeSet<_>>()
+            };
+            assert_eq!(below(85.), BTreeSet::new());
+            assert_eq!(below(86.), vec![a].into_iter().collect());
+            assert_eq!(above(115.), vec![a, b].into_iter().collect());
+            assert_eq!(above(116.), vec![b].into_iter().collect());
+            // `a` is 0.1 radians away, but its extent covers asin(0.15)
+            assert_eq!(between(0.1, 1.), vec![a].into_iter().collect());
+            assert_eq!(between(0.2, 1.), BTreeSet::new());
+            assert_eq!(
+                index.near(&store_body, units::Position(Vector2::new(100., 20.)), 6., t),
+                vec![a]
+            );
+        }
+
+        // removal does not access the despawned entity
+        world.delete_entity(b).unwrap();
+        world.maintain();
+        index.remove(b, t);
+        index.remove(b, t);
+        let store_body = world.read_storage::<Body>();
+        index.update(&store_body, t);
+        assert_eq!(index.all().collect::<Vec<_>>(), vec![a]);
+        assert_eq!(index.above(&store_body, 116., t).count(), 0);
+
+        // inserting again without bounding boxes resets the extent
+        index.insert(a, &store_body, &HashMap::new(), t);
+        index.update(&store_body, t);
+        #[cfg(debug_assertions)]
+        index.check_invariants(&store_body, t);
+        assert_eq!(index.all().collect::<Vec<_>>(), vec![a]);
+        assert_eq!(index.below(&store_body, 99., t).count(), 0);
+        assert_eq!(index.below(&store_body, 101., t).count(), 1);
+    }
+
+    /// Checks whether `position` is in the annular sector.
+    fn in_sector(position: Vector2<f64>, min: f64, max: f64, from: f64, to: f64) -> bool {
+        let radius = position.norm();
+        let offset = (position[1].atan2(position[0]) - from).rem_euclid(PI * 2.);
+        min <= radius && radius <= max && offset <= (to - from).rem_euclid(PI * 2.)
+    }
+
+    /// Computes the distance along a ray at which it first comes within `width` of a circle,
+    /// or `None` if it never does.
+    fn ray_entry(
+        origin: Vector2<f64>,
+        unit: Vector2<f64>,
+        range: f64,
+        width: f64,
+        center: Vector2<f64>,
+        extent: f64,
+    ) -> Option<f64> {
+        let reach = width + extent;
+        // the closest point of the path to the centre
+        let closest = (center - origin).dot(&unit).max(0.).min(range);
+        if (origin + unit * closest - center).norm() > reach {
+            return None;
+        }
+        if (origin - center).norm() <= reach {
+            return Some(0.);
+        }
+        // bisect for the first point of the path within reach
+        let (mut low, mut high) = (0., closest);
+        for _ in 0..100 {
+            let mid = (low + high) / 2.;
+            if (origin + unit * mid - center).norm() <= reach {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+        Some(high)
+    }
+
+    /// Checks random sector, nearest and ray queries on `index` at `t` against brute force
+    /// over `entities`, returning the number of entities found in the sector by extent alone.
+    fn check_queries(
+        index: &BodyIndex,
+        store_body: &impl GenericReadStorage<Component = Body>,
+        extents: &HashMap<Entity, units::Length>,
+        entities: &BTreeSet<Entity>,
+        t: units::GameInstant,
+        seed: &mut u64,
+    ) -> usize {
+        let mut by_extent = 0;
+        let state = |entity: Entity| {
+            let position = store_body.get(entity).unwrap().position(t).0;
+            (position, extents.get(&entity).copied().unwrap_or(0.))
+        };
+
+        let min = random(seed) * 250.;
+        let max = min + random(seed) * 100.;
+        let from = random(seed) * PI * 2.;
+        let to = from + random(seed) * PI;
+        let sector = index.sector(
+            store_body,
+            min,
+            max,
+            units::Bearing(from),
+            units::Bearing(to),
+            t,
+        );
+        let found: BTreeSet<Entity> = sector.iter().copied().collect();
+        assert_eq!(found.len(), sector.len(), "duplicates in {:?}", sector);
+        for &entity in entities {
+            let (position, extent) = state(entity);
+            let center = in_sector(position, min, max, from, to);
+            let touches = center
+                || (0..32).any(|i| {
+                    let angle = f64::from(i) / 32. * PI * 2.;
+                    let point = Vector2::new(angle.cos(), angle.sin()) * extent * 0.999;
+                    in_sector(position + point, min, max, from, to)
+                });
+            // the circle is within the wedge enclosing the sector,
+            // which may include points just outside its corners
+            let radius = position.norm();
+            let allowed = radius - extent <= max
+                && radius + extent >= min
+                && (radius <= extent || overlaps_arc(units::Position(position), extent, from, to));
+            if touches {
+                assert!(found.contains(&entity), "sector misses {:?}", entity);
+                if !center {
+                    by_extent += 1;
+                }
+            }
+            if found.contains(&entity) {
+                assert!(allowed, "sector has {:?} outside the wedge", entity);
+            }
+        }
+
+        let k = (random(seed) * 10.) as usize;
+        let bearing = random(seed) * PI * 2.;
+        let position = Vector2::new(bearing.cos(), bearing.sin()) * random(seed) * 300.;
+        let distance = |entity: Entity| {
+            let (other, extent) = state(entity);
+            ((other - position).norm() - extent).max(0.)
+        };
+        let nearest: Vec<f64> = index
+            .nearest(store_body, units::Position(position), k, t)
+            .into_iter()
+            .map(distance)
+            .collect();
+        let mut expected: Vec<f64> = entities.iter().copied().map(distance).collect();
+        expected.sort_by(f64::total_cmp);
+        expected.truncate(k);
+        assert_eq!(nearest, expected, "nearest {} to {:?}", k, position);
+
+        let direction = random(seed) * PI * 2.;
+        let unit = Vector2::new(direction.cos(), direction.sin());
+        let range = random(seed) * 400.;
+        let width = random(seed) * 3.;
+        let hit = index
+            .ray_cast(
+                store_body,
+                units::Position(position),
+                units::Bearing(direction),
+                range,
+                width,
+                t,
+            )
+            .map(|entity| {
+                let (center, extent) = state(entity);
+                ray_entry(position, unit, range, width, center, extent).expect("hit")
+            });
+        let expected = entities
+            .iter()
+            .filter_map(|&entity| {
+                let (center, extent) = state(entity);
+                ray_entry(position, unit, range, width, center, extent)
+            })
+            .min_by(f64::total_cmp);
+        match (hit, expected) {
+            (Some(hit), Some(expected)) => assert!((hit - expected).abs() < 1e-6),
+            (None, None) => {}
+            _ => panic!("ray cast hits {:?} instead of {:?}", hit, expected),
+        }
+        by_extent
+    }
+
+    #[test]
+    fn spatial_queries() {
+        let (mut world, star) = world();
+        let mut seed = 0x9e37_79b9_7f4a_7c15;
+        let entities: BTreeSet<Entity> = (0..300)
+            .map(|_| spawn_orbiting(&mut world, star, &mut seed))
+            .collect();
+
+        let store_body = world.read_storage::<Body>();
+        let extents = BodyIndex::extents(&world.read_storage::<BoundingBox>());
+        let mut index = BodyIndex::new();
+        for &entity in &entities {
+            index.insert(entity, &store_body, &extents, units::GameInstant(0));
+        }
+
+        // the number of entities in sectors only by their extent
+        let mut by_extent = 0;
+        for tick in 0..200 {
+            let t = units::GameInstant(tick);
+            index.update(&store_body, t);
+            by_extent += check_queries(&index, &store_body, &extents, &entities, t, &mut seed);
+        }
+        assert!(by_extent > 0);
+    }
+
+    #[test]
+    fn accelerating_and_standing() {
+        let (mut world, star) = world();
+        let mut seed = 0x6a09_e667_f3bc_c908;
+        let mut entities: BTreeSet<Entity> = (0..200)
+            .map(|_| spawn_orbiting(&mut world, star, &mut seed))
+            .collect();
+        for i in 0..100 {
+            let radius = 20. + random(&mut seed) * 250.;
+            let bearing = random(&mut seed) * PI * 2.;
+            let position = units::Position(Vector2::new(bearing.cos(), bearing.sin()) * radius);
+            let body = if i < 60 {
+                let velocity = Vector2::new(random(&mut seed) - 0.5, random(&mut seed) - 0.5);
+                Body::Accelerating(AccelBody::new(
+                    position,
+                    units::Velocity::of(units::Displace(velocity * 4.)),
+                    star,
+                ))
+            } else {
+                Body::Standing(StandingBody::new(position, star))
+            };
+            let entity = world.create_entity().with(body).build();
+            spawn_bbs(&mut world, entity, &mut seed);
+            let _ = entities.insert(entity);
+        }
+
+        let extents = BodyIndex::extents(&world.read_storage::<BoundingBox>());
+        let mut index = BodyIndex::new();
+        for &entity in &entities {
+            index.insert(
+                entity,
+                &world.read_storage::<Body>(),
+                &extents,
+                units::GameInstant(0),
+            );
+        }
+
+        let mut by_extent = 0;
+        for tick in 0..300 {
+            let t = units::GameInstant(tick);
+            if tick > 0 {
+                let mut store_body = world.write_storage::<Body>();
+                for &entity in &entities {
+                    if let Some(Body::Accelerating(body)) = store_body.get_mut(entity) {
+                        body.advance(t - units::GameDuration::UNIT, M);
+                    }
+                }
+            }
+            if tick % 25 == 10 {
+                // an orbiting body starts accelerating, and an accelerating body starts orbiting
+                let mut store_body = world.write_storage::<Body>();
+                let orbiting = index.all_orbiting().next().unwrap();
+                let accelerating = index.all_accelerating().next().unwrap();
+                for &entity in &[orbiting, accelerating] {
+                    let body = store_body.get_mut(entity).unwrap();
+                    *body = match body {
+                        Body::Orbiting(body) => Body::Accelerating(body.to_accelerating(t, M)),
+                        Body::Accelerating(body) => Body::Orbiting(body.to_orbiting(t, M)),
+                        _ => unreachable!(),
+                    };
+                    index.remove(entity, t);
+                    index.insert(entity, &store_body, &extents, t);
+                }
+                assert!(index.all_accelerating().any(|entity| entity == orbiting));
+                assert!(index.all_orbiting().any(|entity| entity == accelerating));
+            }
+
+            let store_body = world.read_storage::<Body>();
+            index.update(&store_body, t);
+            #[cfg(debug_assertions)]
+            index.check_invariants(&store_body, t);
+            assert_eq!(index.all().collect::<BTreeSet<_>>(), entities);
+            assert_eq!(index.all().count(), entities.len());
+            assert_eq!(index.all_accelerating().count(), 60);
+            assert_eq!(index.all_standing().count(), 40);
+
+            let radius = 20. + random(&mut seed) * 250.;
+            let below: BTreeSet<Entity> = index.below(&store_body, radius, t).collect();
+            let expected = brute_force(&world, &entities, t, |position, extent| {
+                position.0.norm() - extent < radius
+            });
+            assert_eq!(below, expected, "below {} at {}", radius, tick);
+            let above: BTreeSet<Entity> = index.above(&store_body, radius, t).collect();
+            let expected = brute_force(&world, &entities, t, |position, extent| {
+                position.0.norm() + extent >= radius
+            });
+            assert_eq!(above, expected, "above {} at {}", radius, tick);
+            let from = random(&mut seed) * PI * 2.;
+            let to = from + random(&mut seed) * PI * 2.;
+            let between: BTreeSet<Entity> = index
+                .between(&store_body, units::Bearing(from), units::Bearing(to), t)
+                .collect();
+            let expected = brute_force(&world, &entities, t, |position, extent| {
+                overlaps_arc(position, extent, from, to)
+            });
+            assert_eq!(between, expected, "between {} and {} at {}", from, to, tick);
+
+            by_extent += check_queries(&index, &store_body, &extents, &entities, t, &mut seed);
+        }
+        assert!(by_extent > 0);
     }
 }
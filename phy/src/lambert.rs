@@ -0,0 +1,353 @@
+//! Solves Lambert's problem for intercept trajectories.
+//!
+//! Given two positions around the same star and a time of flight,
+//! the solver finds the conics connecting the two positions.
+//! This uses the universal variable formulation,
+//! which covers elliptic and hyperbolic transfers alike.
+//!
+//! Source: H. D. Curtis, Orbital Mechanics for Engineering Students, Algorithm 5.2.
+
+use std::f64::consts::PI;
+
+use getset::*;
+
+use super::{search, Orbit, OrbitalState, Rotation};
+
+/// Number of bisection iterations used when solving the time-of-flight equation.
+///
+/// Each iteration halves the search interval of the universal variable.
+const BISECTION_ITERATIONS: u32 = 128;
+
+/// The universal variable below which the transfer is too hyperbolic to be computed.
+const MIN_UNIVERSAL: f64 = -1e5;
+
+/// Which way the transfer travels around the star.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Way {
+    /// The transfer sweeps less than half a revolution (not counting complete revolutions).
+    Short,
+    /// The transfer sweeps more than half a revolution (not counting complete revolutions).
+    Long,
+}
+
+impl Way {
+    /// Returns the direction of motion that transfers from `from` to `to` in this way.
+    pub fn rotation(self, from: units::Position, to: units::Position) -> Rotation {
+        let cross = util::cross2d(from.0, to.0);
+        match (self, cross >= 0.) {
+            (Self::Short, true) | (Self::Long, false) => Rotation::Counterclockwise,
+            (Self::Short, false) | (Self::Long, true) => Rotation::Clockwise,
+        }
+    }
+}
+
+/// A trajectory connecting the two positions of a Lambert problem.
+#[derive(Debug, Clone, Copy, Getters, CopyGetters)]
+pub struct Solution {
+    /// The velocity required at the departure position.
+    #[getset(get_copy = "pub")]
+    departure: units::Velocity,
+    /// The velocity on arrival at the target position.
+    #[getset(get_copy = "pub")]
+    arrival: units::Velocity,
+}
+
+impl Solution {
+    /// Computes the orbit of this trajectory, which departs from `from` at time `t`.
+    ///
+    /// `m` is the strength of the parent star.
+    pub fn orbit(&self, from: units::Position, t: units::GameInstant, m: units::Mass) -> Orbit {
+        Orbit::from_states(OrbitalState::new(from, self.departure), t, m)
+    }
+}
+
+/// Finds the trajectories from `from` to `to` taking `time_of_flight`,
+/// moving in the direction `rotation` with `revolutions` complete revolutions in between.
+///
+/// `m` is the strength of the parent star.
+///
+/// Zero-revolution transfers have at most one solution.
+/// Multi-revolution transfers have either zero or two solutions,
+/// the first one having the lower energy.
+///
+/// No solutions are returned if the two positions are collinear with the star,
+/// since the transfer plane is then undetermined.
+pub fn solve(
+    from: units::Position,
+    to: units::Position,
+    time_of_flight: units::GameDuration,
+    m: units::Mass,
+    rotation: Rotation,
+    revolutions: u32,
+) -> Vec<Solution> {
+    let mu = m.0;
+    let r1 = from.0;
+    let r2 = to.0;
+    let r1_norm = r1.norm();
+    let r2_norm = r2.norm();
+
+    // transfer angle along the direction of motion, in (0, 2pi)
+    let angle = util::cross2d(r1, r2).atan2(r1.dot(&r2)) * rotation.signum();
+    let angle = angle.rem_euclid(PI * 2.);
+    if angle.sin().abs() < f64::EPSILON {
+        return vec![];
+    }
+
+    let problem = Problem {
+        r1: r1_norm,
+        r2: r2_norm,
+        a: angle.sin() * (r1_norm * r2_norm / (1. - angle.cos())).sqrt(),
+        mu,
+        time: time_of_flight.as_float(),
+    };
+
+    let roots = if revolutions == 0 {
+        problem
+            .bisect(problem.lowest_universal(), (PI * 2.).powi(2))
+            .into_iter()
+            .collect()
+    } else {
+        let n = f64::from(revolutions);
+        let low = (PI * 2. * n).powi(2);
+        let high = (PI * 2. * (n + 1.)).powi(2);
+        let min = problem.fastest_universal(low, high);
+        if problem.time_of_flight(min) > problem.time {
+            vec![]
+        } else {
+            // the left branch (lower universal variable) has the higher energy
+            let mut roots: Vec<f64> = problem.bisect_descending(low, min).into_iter().collect();
+            roots.extend(problem.bisect(min, high));
+            roots.reverse();
+            roots
+        }
+    };
+
+    roots
+        .into_iter()
+        .map(|z| {
+            let y = problem.y(z);
+            let f = 1. - y / r1_norm;
+            let g = problem.a * (y / mu).sqrt();
+            let g_dot = 1. - y / r2_norm;
+            Solution {
+                departure: units::Velocity::of(units::Displace((r2 - r1 * f) / g)),
+                arrival: units::Velocity::of(units::Displace((r2 * g_dot - r1) / g)),
+            }
+        })
+        .collect()
+}
+
+/// The scalar parameters of a Lambert problem.
+struct Problem {
+    r1: units::Length,
+    r2: units::Length,
+    /// The geometric constant `A`.
+    a: f64,
+    mu: f64,
+    /// The required time of flight.
+    time: f64,
+}
+
+impl Problem {
+    /// The auxiliary function `y(z)`.
+    fn y(&self, z: f64) -> f64 {
+        let (c, s) = stumpff(z);
+        self.r1 + self.r2 + self.a * (z * s - 1.) / c.sqrt()
+    }
+
+    /// The time of flight for the universal variable `z`.
+    ///
+    /// Values of `z` with no real trajectory are mapped to zero,
+    /// which is the limit of the time of flight at the boundary.
+    fn time_of_flight(&self, z: f64) -> f64 {
+        let y = self.y(z);
+        if y.is_nan() || y <= 0. {
+            return 0.;
+        }
+        let (c, s) = stumpff(z);
+        ((y / c).powf(1.5) * s + self.a * y.sqrt()) / self.mu.sqrt()
+    }
+
+    /// Finds a lower bound of the universal variable for zero-revolution transfers,
+    /// below which the time of flight is shorter than required.
+    fn lowest_universal(&self) -> f64 {
+        let mut z = -(PI * 2.).powi(2);
+        while z > MIN_UNIVERSAL && self.time_of_flight(z) > self.time {
+            z *= 2.;
+        }
+        z
+    }
+
+    /// Finds the universal variable with the minimum time of flight in `(low, high)`.
+    fn fastest_universal(&self, low: f64, high: f64) -> f64 {
+        search::minimize(|z| self.time_of_flight(z), low, high)
+    }
+
+    /// Finds the root of the time-of-flight equation in `(low, high)`,
+    /// where the time of flight increases with the universal variable.
+    fn bisect(&self, low: f64, high: f64) -> Option<f64> {
+        self.bisect_by(low, high, |z| self.time_of_flight(z) < self.time)
+    }
+
+    /// Finds the root of the time-of-flight equation in `(low, high)`,
+    /// where the time of flight decreases with the universal variable.
+    fn bisect_descending(&self, low: f64, high: f64) -> Option<f64> {
+        self.bisect_by(low, high, |z| self.time_of_flight(z) > self.time)
+    }
+
+    /// Bisects `(low, high)` for the boundary where `too_low` changes from true to false.
+    fn bisect_by(&self, mut low: f64, mut high: f64, too_low: impl Fn(f64) -> bool) -> Option<f64> {
+        for _ in 0..BISECTION_ITERATIONS {
+            let mid = (low + high) / 2.;
+            if too_low(mid) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        let z = (low + high) / 2.;
+        let error = (self.time_of_flight(z) - self.time).abs();
+        if error <= self.time * 1e-6 + 1e-9 {
+            Some(z)
+        } else {
+            None
+        }
+    }
+}
+
+/// Computes the Stumpff functions `C(z)` and `S(z)`.
+fn stumpff(z: f64) -> (f64, f64) {
+    if z > 1e-6 {
+        let sqrt = z.sqrt();
+        ((1. - sqrt.cos()) / z, (sqrt - sqrt.sin()) / sqrt.powi(3))
+    } else if z < -1e-6 {
+        let sqrt = (-z).sqrt();
+        ((sqrt.cosh() - 1.) / -z, (sqrt.sinh() - sqrt) / sqrt.powi(3))
+    } else {
+        (0.5 - z / 24., 1. / 6. - z / 120.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nalgebra::Vector2;
+
+    use crate::Conic;
+
+    const M: units::Mass = units::Mass(1e4);
+    const DEPART: units::GameInstant = units::GameInstant(1000);
+
+    fn position(radius: f64, bearing: f64) -> units::Position {
+        units::Position(Vector2::new(radius * bearing.cos(), radius * bearing.sin()))
+    }
+
+    /// Asserts that the orbit of `solution` departs from `from` and reaches `to` after `tof`,
+    /// and returns that orbit.
+    fn assert_reaches(
+        solution: &Solution,
+        from: units::Position,
+        to: units::Position,
+        tof: units::GameDuration,
+    ) -> Orbit {
+        let orbit = solution.orbit(from, DEPART, M);
+        let time = f64::from(DEPART.0) + tof.as_float();
+        let reached = orbit.position_at(time, 1e-9);
+        assert!(
+            (reached.0 - to.0).norm() <= to.0.norm() * 1e-6,
+            "{:?} != {:?}",
+            reached,
+            to
+        );
+        let arrival = orbit.velocity_at(time, M, 1e-12);
+        assert!(
+            (arrival.0 .0 - solution.arrival().0 .0).norm() <= arrival.0 .0.norm() * 1e-6,
+            "{:?} != {:?}",
+            arrival,
+            solution.arrival()
+        );
+        orbit
+    }
+
+    #[test]
+    fn way_rotation() {
+        let from = position(10000., 0.);
+        let to = position(15000., 1.);
+        assert_eq!(Way::Short.rotation(from, to), Rotation::Counterclockwise);
+        assert_eq!(Way::Long.rotation(from, to), Rotation::Clockwise);
+        assert_eq!(Way::Short.rotation(to, from), Rotation::Clockwise);
+        assert_eq!(Way::Long.rotation(to, from), Rotation::Counterclockwise);
+    }
+
+    #[test]
+    fn zero_revolutions() {
+        let tof = units::GameDuration(20000);
+        for &(from, to) in &[
+            (position(10000., 0.), position(15000., 1.)),
+            (position(15000., 2.), position(10000., 0.5)),
+            (position(12000., -1.), position(8000., 2.5)),
+        ] {
+            for &way in &[Way::Short, Way::Long] {
+                let rotation = way.rotation(from, to);
+                let solutions = solve(from, to, tof, M, rotation, 0);
+                assert_eq!(solutions.len(), 1, "{:?} {:?} {:?}", from, to, way);
+                let orbit = assert_reaches(&solutions[0], from, to, tof);
+                assert_eq!(orbit.rotation(), rotation);
+                assert_eq!(orbit.conic(), Conic::Elliptic);
+            }
+        }
+    }
+
+    #[test]
+    fn hyperbolic_transfer() {
+        let from = position(10000., 0.);
+        let to = position(15000., 1.);
+        let tof = units::GameDuration(1000);
+        for &rotation in &[Rotation::Counterclockwise, Rotation::Clockwise] {
+            let solutions = solve(from, to, tof, M, rotation, 0);
+            assert_eq!(solutions.len(), 1);
+            let orbit = assert_reaches(&solutions[0], from, to, tof);
+            assert_eq!(orbit.conic(), Conic::Hyperbolic);
+            assert_eq!(orbit.rotation(), rotation);
+        }
+    }
+
+    #[test]
+    fn one_revolution() {
+        let from = position(10000., 0.);
+        let to = position(15000., 1.);
+        for &rotation in &[Rotation::Counterclockwise, Rotation::Clockwise] {
+            // shorter than any one-revolution transfer
+            assert!(solve(from, to, units::GameDuration(20000), M, rotation, 1).is_empty());
+
+            let tof = units::GameDuration(120000);
+            let solutions = solve(from, to, tof, M, rotation, 1);
+            assert_eq!(solutions.len(), 2);
+            let energies: Vec<f64> = solutions
+                .iter()
+                .map(|solution| {
+                    let orbit = assert_reaches(solution, from, to, tof);
+                    assert_eq!(orbit.rotation(), rotation);
+                    assert_eq!(orbit.conic(), Conic::Elliptic);
+                    // more than one revolution, but less than two
+                    let period = orbit.period().expect("elliptic").as_float();
+                    assert!(period < tof.as_float() && tof.as_float() < period * 2.);
+                    orbit.specific_energy(M)
+                })
+                .collect();
+            assert!(energies[0] < energies[1], "{:?}", energies);
+        }
+    }
+
+    #[test]
+    fn degenerate_inputs() {
+        let from = position(10000., 0.);
+        let tof = units::GameDuration(20000);
+        for &to in &[position(15000., 0.), position(15000., PI), from] {
+            for &rotation in &[Rotation::Counterclockwise, Rotation::Clockwise] {
+                assert!(solve(from, to, tof, M, rotation, 0).is_empty());
+            }
+        }
+    }
+}
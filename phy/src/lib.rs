@@ -5,10 +5,17 @@
 #![cfg_attr(debug_assertions, allow(unused_variables, dead_code, unreachable_code))]
 #![warn(missing_docs)]
 
+mod approach;
+pub use approach::Approach;
 mod body;
-pub use body::{Body, Star};
+pub use body::{AccelBody, Attitude, Body, Inertia, OrbitingBody, RootBody, StandingBody, Star};
 pub mod collision;
 mod orbit;
-pub use orbit::{Orbit, OrbitalState};
+pub use orbit::{Conic, ElementsError, Orbit, OrbitalElements, OrbitalState, Rotation};
 mod index;
 pub use index::BodyIndex;
+pub mod lambert;
+pub mod planner;
+pub mod predict;
+mod search;
+pub mod soi;
@@ -1,31 +1,126 @@
-// TODO check if some `%(2*PI)` operations can be elided by defining precisely the anomaly ranges.
-// Maybe change units::Theta to units::Bearing for anomalies?
-
 use std::cmp::Ordering;
+use std::f64::consts::PI;
+use std::fmt;
+
+use getset::*;
+use nalgebra::Vector2;
+
+/// Maximum number of Newton iterations used when solving Kepler's equation.
+const KEPLER_ITERATIONS: u32 = 64;
+
+/// Eccentricities closer to 1 than this value are treated as parabolic.
+const PARABOLIC_TOLERANCE: f64 = 1e-9;
+
+/// Number of ticks subtracted from computed crossing times,
+/// so that floating-point errors never move a lower bound past the actual crossing.
+const CROSSING_MARGIN: f64 = 1e-6;
+
+/// The kind of conic section traced by an orbit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conic {
+    /// A closed orbit with eccentricity less than 1, or a bound radial trajectory.
+    Elliptic,
+    /// An escape trajectory with eccentricity equal to 1.
+    Parabolic,
+    /// An escape trajectory with eccentricity greater than 1, or an unbound radial trajectory.
+    Hyperbolic,
+}
 
-use units::LengthExt;
+impl Conic {
+    /// Classifies a conic section by its eccentricity.
+    ///
+    /// Eccentricities very close to 1 are classified as parabolic.
+    pub fn from_eccentricity(eccentricity: f64) -> Self {
+        if (eccentricity - 1.).abs() < PARABOLIC_TOLERANCE {
+            Self::Parabolic
+        } else if eccentricity < 1. {
+            Self::Elliptic
+        } else {
+            Self::Hyperbolic
+        }
+    }
+}
+
+/// The direction in which a body travels along its orbit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    /// The bearing of the body increases over time.
+    Counterclockwise,
+    /// The bearing of the body decreases over time.
+    Clockwise,
+}
+
+impl Rotation {
+    /// Determines the direction of motion from the sign of the angular momentum.
+    ///
+    /// Radial trajectories with zero angular momentum are regarded as counterclockwise.
+    pub fn from_ang_momentum(ang_momentum: f64) -> Self {
+        if ang_momentum < 0. {
+            Self::Clockwise
+        } else {
+            Self::Counterclockwise
+        }
+    }
+
+    /// Returns 1 for counterclockwise rotation and -1 for clockwise rotation.
+    pub fn signum(self) -> f64 {
+        match self {
+            Self::Counterclockwise => 1.,
+            Self::Clockwise => -1.,
+        }
+    }
+}
 
 /// Represents a Keplerian orbit.
 ///
 /// This struct represents the trajectory of a Keplerian orbit in a star system.
 /// This is isomorphic to an `OrbitalState` given a specific mass and time frame.
 ///
+/// Escape trajectories are represented by the same struct.
+/// For such orbits, "eccentric anomaly" refers to the hyperbolic anomaly `H`
+/// for hyperbolic orbits and `D = tan(nu / 2)` for parabolic orbits,
+/// and the mean anomaly is not periodic.
+///
+/// All anomalies are measured along the direction of motion,
+/// so they increase over time for both counterclockwise and clockwise orbits.
+///
+/// Radial trajectories, which have no angular momentum, are degenerate conics
+/// with eccentricity 1 and a zero semi-latus rectum.
+/// They are classified as elliptic or hyperbolic by their energy,
+/// and keep the finite semimajor axis, so the usual Kepler equations still apply.
+/// The body moves along a fixed line from the star, in the direction opposite to the periapsis,
+/// and an elliptic radial trajectory falls back to the star and bounces off it.
+///
 /// Extra data are stored in this struct for efficient computation.
-#[derive(Debug)]
+#[derive(Debug, Clone, Getters, CopyGetters)]
 pub struct Orbit {
-    /// orbit eccentricity, a value between 0 and 1
+    /// The kind of conic section
+    conic: Conic,
+    /// orbit eccentricity, a non-negative value (exactly 1 for parabolic and radial orbits)
+    #[getset(get_copy = "pub")]
     eccentricity: f64,
-    /// Ratio of `tan(nu / 2)` to `tan(E / 2)`.
+    /// Ratio of `tan(nu / 2)` to `tan(E / 2)` (or `tanh(H / 2)` for hyperbolic orbits).
     ///
-    /// This is equal to `sqrt((1 + e) / (1 - e))`.
+    /// This is equal to `sqrt((1 + e) / |1 - e|)`, or 1 for parabolic orbits.
     te_ratio: f64,
-    /// Mean length of the apsides
+    /// Mean length of the apsides.
+    ///
+    /// This is negative for hyperbolic orbits and infinite for parabolic orbits.
+    #[getset(get_copy = "pub")]
     semimajor: units::Length,
+    /// Semi-latus rectum, which is finite for all conics and zero for radial trajectories
+    slr: units::Length,
     /// Argument of periapsis, an angle value
+    #[getset(get_copy = "pub")]
     periapsis: units::Bearing,
+    /// The direction of motion along the orbit
+    rotation: Rotation,
+    /// The instant at which `epoch_anomaly` is measured
+    epoch: units::GameInstant,
     /// Mean anomaly at epoch
     epoch_anomaly: MeanAnomaly,
     /// Rate of change of mean anomaly
+    #[getset(get_copy = "pub")]
     average_sweep: units::Omega,
 }
 
@@ -33,7 +128,6 @@ impl Orbit {
     /// Computes the `Orbit` parameters at a specific `OrbitalState`.
     pub fn from_states(state: OrbitalState, t: units::GameInstant, m: units::Mass) -> Self {
         // TODO optimize float precision
-        // TODO optimize parameters
 
         let mu = m.0; // TODO tune the modifier for gravity coefficient
 
@@ -44,42 +138,306 @@ impl Orbit {
         let r_norm = r.norm();
         let v = state.velocity.0 .0;
 
-        // 1. Angular momentum (h). Dimension: M L^2 T^-1
-        let ang_momentum: f64 = r[0] * v[1] - r[1] * v[0];
+        // 1. Angular momentum (h). Dimension: L^2 T^-1
+        let ang_momentum: f64 = util::cross2d(r, v);
+        // 2. Eccentricity vector. Dimension: 1
+        let ecc_vector = (r * (v.norm_squared() - mu / r_norm) - v * r.dot(&v)) / mu;
         // 3. Specific energy (epsilon). Dimension: L^2 T^-2
         let energy: f64 = v.norm_squared() / 2. - mu / r_norm;
         // 4. Semimajor axis (a). Dimension: L
         let semimajor: units::Length = -mu / 2. / energy;
         // 5. Eccentricity (e). Dimension: 1
-        let eccentricity: f64 = (1. - ang_momentum.powi(2) / (semimajor * mu)).sqrt();
+        let eccentricity: f64 = ecc_vector.norm();
         // 8. Argument of latitude (u). Dimension: 1 (angle)
-        let latitude: units::Bearing = units::Bearing(todo!());
+        let latitude: units::Bearing = units::Bearing(r[1].atan2(r[0]));
         // 9a. Semi-latus rectum (p). Dimension: L
-        let slr: units::Length = semimajor * (1. - eccentricity.powi(2));
-        // 9b. True anomaly (nu). Dimension: 1 (angle)
-        let true_anomaly: units::Theta = (slr - r_norm).arccos(r_norm * eccentricity);
+        let slr: units::Length = ang_momentum.powi(2) / mu;
         // 10. Argument of periapsis (omega). Dimension: 1 (angle)
-        let periapsis: units::Bearing = latitude - true_anomaly;
+        let periapsis: units::Bearing = units::Bearing(ecc_vector[1].atan2(ecc_vector[0]));
+        // Direction of motion, which the true anomaly is measured along
+        let rotation = Rotation::from_ang_momentum(ang_momentum);
 
-        // Average sweep (n). Dimension: T^-1
-        let average_sweep = units::Omega::of(units::Theta((mu / semimajor.powi(3)).sqrt()));
+        // A parabolic approximation ignores the energy, which is only accurate far beyond `r^2 / p`,
+        // while a radial approximation ignores the angular momentum,
+        // which is only accurate far beyond `p`.
+        let radial = Conic::from_eccentricity(eccentricity) == Conic::Parabolic
+            && (slr == 0. || slr * semimajor.abs() < r_norm.powi(2));
+        if radial {
+            return Self::radial(r, v, semimajor, t, m);
+        }
 
-        // Eccentricity anomaly (E). Dimension: 1 (angle)
-        let ecc_anomaly = r[0].arccos(semimajor);
+        // 9b. True anomaly (nu). Dimension: 1 (angle)
+        let true_anomaly = TrueAnomaly((latitude - periapsis) * rotation.signum());
 
+        let mut orbit = Self::with_shape(eccentricity, semimajor, slr, periapsis, rotation, t, m);
         // Mean anomaly (M). Dimension: 1 (angle)
-        let mean_anomaly =
-            MeanAnomaly(ecc_anomaly - units::Theta(eccentricity * ecc_anomaly.sin()));
+        orbit.epoch_anomaly = orbit.ea_to_ma(orbit.ta_to_ea(true_anomaly));
+        orbit
+    }
 
-        let epoch_anomaly = mean_anomaly - average_sweep.after(t.since_epoch());
+    /// Computes the radial trajectory through position `r` with velocity `v` at time `t`.
+    ///
+    /// Any angular momentum is discarded.
+    /// Trajectories at exactly the escape velocity are rounded to a hyperbolic trajectory
+    /// whose energy is within the parabolic tolerance of zero.
+    fn radial(
+        r: Vector2<f64>,
+        v: Vector2<f64>,
+        semimajor: units::Length,
+        t: units::GameInstant,
+        m: units::Mass,
+    ) -> Self {
+        let r_norm = r.norm();
+        let semimajor = if semimajor.is_finite() {
+            semimajor
+        } else {
+            -r_norm / PARABOLIC_TOLERANCE
+        };
+        // the periapsis is at the star, on the opposite side of the body
+        let periapsis = units::Bearing(r[1].atan2(r[0]) + PI);
+
+        let mut orbit = Self::with_shape(
+            1.,
+            semimajor,
+            0.,
+            periapsis,
+            Rotation::Counterclockwise,
+            t,
+            m,
+        );
+        // the eccentric anomaly is positive when moving away from the star
+        let ea = orbit.radius_to_ea(r_norm).0 .0.copysign(r.dot(&v));
+        orbit.epoch_anomaly = orbit.ea_to_ma(EccenAnomaly(units::Theta(ea)));
+        orbit
+    }
+
+    /// Computes the `Orbit` from classical orbital elements.
+    ///
+    /// `elements.mean_anomaly` is the mean anomaly at time `t`,
+    /// and `m` is the strength of the parent star.
+    ///
+    /// Parabolic orbits cannot be expressed with a semimajor axis;
+    /// use `Orbit::from_states` to construct them instead.
+    pub fn from_elements(
+        elements: OrbitalElements,
+        t: units::GameInstant,
+        m: units::Mass,
+    ) -> Result<Self, ElementsError> {
+        let OrbitalElements {
+            semimajor,
+            eccentricity,
+            periapsis,
+            rotation,
+            mean_anomaly,
+        } = elements;
+
+        if !(m.0 > 0. && m.0.is_finite()) {
+            return Err(ElementsError::Strength);
+        }
+        if !(eccentricity >= 0. && eccentricity.is_finite()) {
+            return Err(ElementsError::Eccentricity);
+        }
+        if !(periapsis.0.is_finite() && mean_anomaly.0.is_finite()) {
+            return Err(ElementsError::Angle);
+        }
+        let conic = Conic::from_eccentricity(eccentricity);
+        let semimajor_valid = match conic {
+            Conic::Elliptic => semimajor > 0. && semimajor.is_finite(),
+            Conic::Hyperbolic => semimajor < 0. && semimajor.is_finite(),
+            Conic::Parabolic => return Err(ElementsError::Parabolic),
+        };
+        if !semimajor_valid {
+            return Err(ElementsError::Semimajor);
+        }
+
+        let slr = semimajor * (1. - eccentricity.powi(2));
+        let mut orbit = Self::with_shape(eccentricity, semimajor, slr, periapsis, rotation, t, m);
+        orbit.epoch_anomaly = match conic {
+            Conic::Elliptic => MeanAnomaly(units::Theta(normalize_angle(mean_anomaly.0))),
+            _ => MeanAnomaly(mean_anomaly),
+        };
+        Ok(orbit)
+    }
+
+    /// Constructs an orbit with the given shape and a zero mean anomaly at `epoch`.
+    ///
+    /// Eccentricities close to 1 are rounded to exactly 1 for parabolic orbits.
+    /// A zero `slr` constructs a radial trajectory, classified by the sign of `semimajor`.
+    fn with_shape(
+        eccentricity: f64,
+        semimajor: units::Length,
+        slr: units::Length,
+        periapsis: units::Bearing,
+        rotation: Rotation,
+        epoch: units::GameInstant,
+        m: units::Mass,
+    ) -> Self {
+        let mu = m.0;
+
+        let conic = if slr == 0. {
+            if semimajor > 0. {
+                Conic::Elliptic
+            } else {
+                Conic::Hyperbolic
+            }
+        } else {
+            Conic::from_eccentricity(eccentricity)
+        };
+        let (eccentricity, semimajor) = match conic {
+            Conic::Parabolic => (1., f64::INFINITY),
+            _ => (eccentricity, semimajor),
+        };
+
+        let (te_ratio, average_sweep) = match conic {
+            Conic::Elliptic => (
+                ((1. + eccentricity) / (1. - eccentricity)).sqrt(),
+                (mu / semimajor.powi(3)).sqrt(),
+            ),
+            Conic::Hyperbolic => (
+                ((eccentricity + 1.) / (eccentricity - 1.)).sqrt(),
+                (mu / (-semimajor).powi(3)).sqrt(),
+            ),
+            Conic::Parabolic => (1., (mu / slr.powi(3)).sqrt() * 2.),
+        };
 
         Self {
+            conic,
             eccentricity,
-            te_ratio: ((1. + eccentricity) / (1. - eccentricity)).sqrt(),
+            te_ratio,
             semimajor,
+            slr,
             periapsis,
-            epoch_anomaly,
-            average_sweep,
+            rotation,
+            epoch,
+            epoch_anomaly: MeanAnomaly(units::Theta(0.)),
+            // Average sweep (n). Dimension: T^-1
+            average_sweep: units::Omega::of(units::Theta(average_sweep)),
+        }
+    }
+
+    /// Returns the kind of conic section traced by this orbit.
+    pub fn conic(&self) -> Conic {
+        self.conic
+    }
+
+    /// Returns the direction of motion along this orbit.
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Whether this orbit is a radial trajectory, i.e. it has no angular momentum.
+    ///
+    /// The bearing of a body on a radial trajectory never changes.
+    pub fn is_radial(&self) -> bool {
+        self.slr == 0.
+    }
+
+    /// Computes the `OrbitalState` of the orbit at time `t`.
+    ///
+    /// This is the inverse of `Orbit::from_states`,
+    /// computed to the best precision available.
+    pub fn to_state(&self, t: units::GameInstant, m: units::Mass) -> OrbitalState {
+        OrbitalState {
+            position: self.approx_position(t, 0.),
+            velocity: self.approx_velocity(t, m, 0.),
+        }
+    }
+
+    /// Computes the new orbit after an instantaneous velocity change of `delta_v` at time `t`.
+    ///
+    /// `m` is the strength of the parent star.
+    /// The resulting orbit may be of a different conic kind,
+    /// e.g. a sufficiently large prograde impulse turns an elliptic orbit into an escape trajectory.
+    pub fn apply_impulse(
+        &self,
+        t: units::GameInstant,
+        delta_v: units::Velocity,
+        m: units::Mass,
+    ) -> Self {
+        let mut state = self.to_state(t, m);
+        state.velocity = state.velocity + delta_v;
+        Self::from_states(state, t, m)
+    }
+
+    /// Returns the semi-latus rectum of the orbit.
+    ///
+    /// Unlike the semimajor axis, this is finite for all conics.
+    pub fn semi_latus_rectum(&self) -> units::Length {
+        self.slr
+    }
+
+    /// The distance of the periapsis from the star.
+    pub fn periapsis_radius(&self) -> units::Length {
+        self.slr / (1. + self.eccentricity)
+    }
+
+    /// The distance of the apoapsis from the star, or `None` for escape trajectories.
+    pub fn apoapsis_radius(&self) -> Option<units::Length> {
+        match self.conic {
+            Conic::Elliptic => Some(self.semimajor * (1. + self.eccentricity)),
+            _ => None,
+        }
+    }
+
+    /// The time taken for one revolution, rounded to the nearest tick,
+    /// or `None` for escape trajectories.
+    ///
+    /// The exact period is generally not a whole number of ticks,
+    /// so the body is only approximately back at the same position after this duration.
+    pub fn period(&self) -> Option<units::GameDuration> {
+        match self.conic {
+            Conic::Elliptic => Some(round_duration(PI * 2. / self.average_sweep.0 .0)),
+            _ => None,
+        }
+    }
+
+    /// Computes the specific orbital energy, i.e. the total energy per unit mass of the body.
+    ///
+    /// `m` is the strength of the parent star.
+    /// The energy is negative for elliptic orbits, zero for parabolic orbits
+    /// and positive for hyperbolic orbits.
+    pub fn specific_energy(&self, m: units::Mass) -> f64 {
+        match self.conic {
+            Conic::Parabolic => 0.,
+            _ => -m.0 / 2. / self.semimajor,
+        }
+    }
+
+    /// Computes the specific angular momentum, i.e. the angular momentum per unit mass of the body.
+    ///
+    /// `m` is the strength of the parent star.
+    /// The value is positive for counterclockwise orbits and negative for clockwise orbits.
+    pub fn angular_momentum(&self, m: units::Mass) -> f64 {
+        (m.0 * self.slr).sqrt() * self.rotation.signum()
+    }
+
+    /// Computes the time from `t` until the body next passes the periapsis,
+    /// rounded up to the next tick.
+    ///
+    /// Returns `None` if the body is on an escape trajectory and has already passed the periapsis.
+    pub fn time_to_periapsis(&self, t: units::GameInstant) -> Option<units::GameDuration> {
+        let MeanAnomaly(units::Theta(ma)) = self.mean_anomaly_at(f64::from(t.0));
+        let remaining = if ma <= 0. {
+            -ma
+        } else if self.conic == Conic::Elliptic {
+            PI * 2. - ma
+        } else {
+            return None;
+        };
+        Some(ceil_duration(remaining / self.average_sweep.0 .0))
+    }
+
+    /// Computes the time from `t` until the body next passes the apoapsis,
+    /// rounded up to the next tick.
+    ///
+    /// Returns `None` for escape trajectories, which have no apoapsis.
+    pub fn time_to_apoapsis(&self, t: units::GameInstant) -> Option<units::GameDuration> {
+        match self.conic {
+            Conic::Elliptic => {
+                let MeanAnomaly(units::Theta(ma)) = self.mean_anomaly_at(f64::from(t.0));
+                Some(ceil_duration((PI - ma) / self.average_sweep.0 .0))
+            }
+            _ => None,
         }
     }
 
@@ -92,7 +450,22 @@ impl Orbit {
         t: units::GameInstant,
         tolerance: units::Length,
     ) -> units::Position {
-        todo!()
+        self.position_at(f64::from(t.0), tolerance)
+    }
+
+    /// Approximate the position of the orbit at `time` ticks after `GameInstant::EPOCH`,
+    /// which may be between two ticks.
+    pub(crate) fn position_at(&self, time: f64, tolerance: units::Length) -> units::Position {
+        let ea = self.ma_to_ea(self.mean_anomaly_at(time), tolerance).0 .0;
+        let e = self.eccentricity;
+        let a = self.semimajor;
+        let (x, y) = match self.conic {
+            _ if self.is_radial() => (-self.ea_radius(EccenAnomaly(units::Theta(ea))), 0.),
+            Conic::Elliptic => (a * (ea.cos() - e), a * (1. - e * e).sqrt() * ea.sin()),
+            Conic::Hyperbolic => (a * (ea.cosh() - e), -a * (e * e - 1.).sqrt() * ea.sinh()),
+            Conic::Parabolic => (self.slr / 2. * (1. - ea * ea), self.slr * ea),
+        };
+        units::Position::origin() + self.perifocal(x, y)
     }
 
     /// Approximate the velocity of the orbit at time `t`.
@@ -105,7 +478,42 @@ impl Orbit {
         m: units::Mass,
         tolerance: units::Length,
     ) -> units::Velocity {
-        todo!()
+        self.velocity_at(f64::from(t.0), m, tolerance)
+    }
+
+    /// Approximate the velocity of the orbit at `time` ticks after `GameInstant::EPOCH`,
+    /// which may be between two ticks.
+    pub(crate) fn velocity_at(
+        &self,
+        time: f64,
+        m: units::Mass,
+        tolerance: units::Length,
+    ) -> units::Velocity {
+        let mu = m.0;
+
+        // velocity components change roughly `n / |1 - e|` times as fast as position components
+        let position_tolerance =
+            tolerance * (1. - self.eccentricity).abs() / self.average_sweep.0 .0;
+        let ea = self.ma_to_ea(self.mean_anomaly_at(time), position_tolerance);
+        let radius = self.ea_radius(ea);
+        let ea = ea.0 .0;
+        let e = self.eccentricity;
+        let a = self.semimajor;
+        let (x, y) = match self.conic {
+            Conic::Elliptic => {
+                let k = (mu * a).sqrt() / radius;
+                (-k * ea.sin(), k * (1. - e * e).sqrt() * ea.cos())
+            }
+            Conic::Hyperbolic => {
+                let k = (-mu * a).sqrt() / radius;
+                (-k * ea.sinh(), k * (e * e - 1.).sqrt() * ea.cosh())
+            }
+            Conic::Parabolic => {
+                let k = (mu * self.slr).sqrt() / radius;
+                (-k * ea, k)
+            }
+        };
+        units::Velocity::of(self.perifocal(x, y))
     }
 
     /// Approximate the bearing of the orbit at time `t`.
@@ -115,7 +523,49 @@ impl Orbit {
         m: units::Mass,
         tolerance: units::Theta,
     ) -> units::Bearing {
-        todo!()
+        self.bearing_at(f64::from(t.0), tolerance)
+    }
+
+    /// Approximate the bearing of the orbit at `time` ticks after `GameInstant::EPOCH`,
+    /// which may be between two ticks.
+    pub(crate) fn bearing_at(&self, time: f64, tolerance: units::Theta) -> units::Bearing {
+        // the body is never closer than the periapsis radius
+        let position_tolerance = tolerance.0 * self.periapsis_radius();
+        let ea = self.ma_to_ea(self.mean_anomaly_at(time), position_tolerance);
+        self.ta_to_bearing(self.ea_to_ta(ea))
+    }
+
+    /// Computes the bearing at `time` ticks after `GameInstant::EPOCH`,
+    /// normalized to `[0, 2pi)`.
+    pub(crate) fn normal_bearing_at(&self, time: f64, tolerance: units::Theta) -> f64 {
+        let bearing = self.bearing_at(time, tolerance).0.rem_euclid(PI * 2.);
+        // `rem_euclid` rounds tiny negative values up to `2pi`
+        if bearing < PI * 2. {
+            bearing
+        } else {
+            0.
+        }
+    }
+
+    /// Computes the bearing at `time` ticks after `GameInstant::EPOCH`
+    /// as a continuous function of time, i.e. without wrapping at `2pi`.
+    ///
+    /// Each counterclockwise revolution adds `2pi`, and each clockwise revolution subtracts `2pi`.
+    pub(crate) fn unwrapped_bearing_at(&self, time: f64) -> f64 {
+        if self.is_radial() {
+            return self.periapsis.0 + PI;
+        }
+        let ma = self.mean_anomaly_at(time);
+        let ta = self.ea_to_ta(self.ma_to_ea(ma, 0.)).0 .0;
+        let revolutions = match self.conic {
+            Conic::Elliptic => {
+                let elapsed = time - f64::from(self.epoch.0);
+                let unwrapped = self.epoch_anomaly.0 .0 + self.average_sweep.0 .0 * elapsed;
+                ((unwrapped - ma.0 .0) / (PI * 2.)).round()
+            }
+            _ => 0.,
+        };
+        self.periapsis.0 + (ta + revolutions * PI * 2.) * self.rotation.signum()
     }
 
     /// Approximate the distance of the orbit from origin at time `t`.
@@ -125,7 +575,13 @@ impl Orbit {
         m: units::Mass,
         tolerance: units::Length,
     ) -> units::Length {
-        todo!()
+        self.radius_at(f64::from(t.0), tolerance)
+    }
+
+    /// Approximate the distance of the orbit from origin at `time` ticks after
+    /// `GameInstant::EPOCH`, which may be between two ticks.
+    pub(crate) fn radius_at(&self, time: f64, tolerance: units::Length) -> units::Length {
+        self.ea_radius(self.ma_to_ea(self.mean_anomaly_at(time), tolerance))
     }
 
     /// Returns an efficient function to determine whether the orbit has radius greater than
@@ -134,93 +590,112 @@ impl Orbit {
         &self,
         radius: units::Length,
     ) -> impl Fn(units::GameInstant) -> bool + 'static {
-        // cosine of eccentric anomaly at intersection
-        let cos_e = 1. / self.eccentricity - radius / (self.semimajor * self.eccentricity);
-        let threshold = if -1.0 < cos_e && cos_e < 1.0 {
-            let ecc_anomaly = cos_e.acos();
-            Some(ecc_anomaly - self.eccentricity * ecc_anomaly.sin())
+        // The radius increases with the magnitude of the mean anomaly,
+        // so the orbit is outside the circle iff `|M|` exceeds the mean anomaly at intersection.
+        let threshold = if radius < self.periapsis_radius() {
+            -1.
+        } else if matches!(self.apoapsis_radius(), Some(apo) if radius >= apo) {
+            f64::INFINITY
         } else {
-            None
+            let ea = self.radius_to_ea(radius);
+            self.ea_to_ma(ea).0 .0
         };
-        let epoch_anomaly = self.epoch_anomaly;
-        let average_sweep = self.average_sweep;
-        move |time| match threshold {
-            Some(threshold) => {
-                let anomaly = epoch_anomaly + average_sweep.after(time.since_epoch());
-                let mut anomaly = anomaly.0 % units::Theta::whole_ac();
-                if anomaly.0 < 0. {
-                    anomaly += units::Theta::whole_ac();
-                }
-                threshold < anomaly.0 && anomaly.0 < (units::Theta::whole_ac().0 - threshold)
-            }
-            None => true,
-        }
+        let mean_anomaly = self.mean_anomaly_fn();
+        move |time| mean_anomaly(f64::from(time.0)).0 .0.abs() > threshold
     }
 
-    /// Compare which orbit has radius.
+    /// Compares the distances of the two orbits from the star at time `t`.
     ///
-    /// `Ordering::Greater` implies that `other` has a shorter arc to `self` along the clockwise
-    /// direction than along the counterclockwise direction.
+    /// `tolerance` is the precision used to compute each radius.
+    /// The computed radii are then compared exactly,
+    /// so `Ordering::Equal` is only returned if both computed radii are identical,
+    /// e.g. if the two orbits are the same.
     ///
-    /// If the difference between the two radii is less than floating point precision in internal
-    /// operations, `Ordering::Equal` may be returned.
+    /// Each radius is computed independently of the other orbit,
+    /// so comparisons with the same `t` and `tolerance` form a total order suitable for sorting.
     pub fn compare_radius(
         &self,
         other: &Self,
         t: units::GameInstant,
         tolerance: units::Length,
     ) -> Ordering {
-        todo!()
+        let time = f64::from(t.0);
+        self.radius_at(time, tolerance)
+            .total_cmp(&other.radius_at(time, tolerance))
     }
 
-    /// Compare which orbit has greater bearing along the minor arc.
+    /// Compares the bearings of the two orbits at time `t`.
     ///
-    /// `Ordering::Greater` implies that `self` has a shorter arc to `other` along the clockwise
-    /// direction than along the counterclockwise direction.
+    /// Bearings are normalized to `[0, 2pi)`, measured counterclockwise from the zero bearing,
+    /// so the order wraps at the zero bearing:
+    /// a bearing slightly clockwise of zero (i.e. close to `2pi`) is greater than
+    /// a bearing slightly counterclockwise of zero.
+    /// Users of this order that need cyclic adjacency
+    /// should treat the first and last orbits in sorted order as neighbours.
     ///
-    /// If the difference between the two arcs (in the mod-2pi field) is less than floating point
-    /// precision in internal operations, `Ordering::Equal` may be returned.
+    /// `tolerance` is the precision used to compute each bearing.
+    /// The computed bearings are then compared exactly,
+    /// so `Ordering::Equal` is only returned if both computed bearings are identical,
+    /// and comparisons with the same `t` and `tolerance` form a total order suitable for sorting.
     pub fn compare_bearing(
         &self,
         other: &Self,
         t: units::GameInstant,
-        tolerance: units::Length,
+        tolerance: units::Theta,
     ) -> Ordering {
-        todo!()
+        let time = f64::from(t.0);
+        self.normal_bearing_at(time, tolerance)
+            .total_cmp(&other.normal_bearing_at(time, tolerance))
     }
 
     /// Tests whether the bearing is in the arc starting from `low`, extending counterclockwise until `high`.
+    ///
+    /// For escape trajectories, bearings beyond the asymptotes are never in range.
     pub fn bearing_in_range(
         &self,
         low: units::Bearing,
         high: units::Bearing,
-        t: units::GameInstant,
     ) -> impl Fn(units::GameInstant) -> bool {
-        let low = self.bearing_to_ta(low);
-        let low = self.ta_to_ea(low);
-        let low = self.ea_to_ma(low);
-        let mut low = low.0 % units::Theta::whole_ac();
-        if low.0 < 0.0 {
-            low += units::Theta::whole_ac();
-        }
-
-        let high = self.bearing_to_ta(high);
-        let high = self.ta_to_ea(high);
-        let high = self.ea_to_ma(high);
-        let mut high = high.0 % units::Theta::whole_ac();
-        if high.0 < 0.0 {
-            high += units::Theta::whole_ac();
-        }
-        if high.0 < low.0 {
-            high += units::Theta::whole_ac();
-        }
+        let arc = (high - low).0.rem_euclid(PI * 2.);
+        // the counterclockwise arc ends at the lower true anomaly for clockwise orbits
+        let low = match self.rotation {
+            Rotation::Counterclockwise => low,
+            Rotation::Clockwise => high,
+        };
+        let low = normalize_angle(self.bearing_to_ta(low).0 .0);
+        let high = low + arc;
+        let limit = self.max_true_anomaly();
 
-        let epoch_anomaly = self.epoch_anomaly;
-        let average_sweep = self.average_sweep;
+        // The arc may wrap around the apoapsis, so it is split into two true anomaly ranges,
+        // each converted to a mean anomaly range.
+        let ranges: Vec<(f64, f64)> = if self.is_radial() {
+            // the true anomaly of a radial trajectory is constant
+            if (PI - low).rem_euclid(PI * 2.) <= arc {
+                vec![(f64::NEG_INFINITY, f64::INFINITY)]
+            } else {
+                vec![]
+            }
+        } else {
+            [0., PI * 2.]
+                .iter()
+                .filter_map(|shift| {
+                    let from = (low - shift).max(-limit);
+                    let to = (high - shift).min(limit);
+                    if from <= to {
+                        let from = self.ea_to_ma(self.ta_to_ea(TrueAnomaly(units::Theta(from))));
+                        let to = self.ea_to_ma(self.ta_to_ea(TrueAnomaly(units::Theta(to))));
+                        Some((from.0 .0, to.0 .0))
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        };
 
+        let mean_anomaly = self.mean_anomaly_fn();
         move |time| {
-            let MeanAnomaly(ma) = epoch_anomaly + average_sweep.after(t.since_epoch());
-            low < ma && ma < low
+            let MeanAnomaly(units::Theta(ma)) = mean_anomaly(f64::from(time.0));
+            ranges.iter().any(|&(from, to)| from <= ma && ma <= to)
         }
     }
 
@@ -231,14 +706,37 @@ impl Orbit {
     /// orbit must not cross the `radius` circle. This method is not guaranteed to return the ideal
     /// lower bound, but is designed to converge towards the intersection efficiently.
     ///
+    /// The crossing is computed analytically, and the returned value is the last tick not after
+    /// the crossing, so the orbit crosses the circle within one tick after the returned instant.
+    ///
     /// This method returns `None` if the orbit can never, or not in computationally relevant time,
     /// intersect with `radius`.
+    /// Circles at the apsides are touched but never crossed, so they also return `None`.
     pub fn when_intersect_radius(
         &self,
         radius: units::Length,
         after: units::GameInstant,
     ) -> Option<units::GameInstant> {
-        todo!()
+        // Circles outside the apsides are never crossed, and circles at an apsis are only touched.
+        if radius <= self.periapsis_radius()
+            || matches!(self.apoapsis_radius(), Some(apo) if radius >= apo)
+        {
+            return None;
+        }
+
+        // Consistent with `radius_comparator`, the crossings are where `|M|` equals `threshold`.
+        let threshold = self.ea_to_ma(self.radius_to_ea(radius)).0 .0;
+        let MeanAnomaly(units::Theta(ma)) = self.mean_anomaly_at(f64::from(after.0));
+        let delta = match self.conic {
+            Conic::Elliptic => (threshold - ma)
+                .rem_euclid(PI * 2.)
+                .min((-threshold - ma).rem_euclid(PI * 2.)),
+            _ if ma < -threshold => -threshold - ma,
+            _ if ma < threshold => threshold - ma,
+            _ => return None,
+        };
+
+        lower_bound_instant(after, delta / self.average_sweep.0 .0)
     }
 
     /// Computes the time (starting from `after`) when the bearings of the two orbits have less
@@ -253,43 +751,408 @@ impl Orbit {
         delta: units::Theta,
         after: units::GameInstant,
     ) -> Option<units::GameInstant> {
-        todo!()
+        // Only the bounds of the angular velocities are used,
+        // so the result is conservative for eccentric orbits.
+        let time = f64::from(after.0);
+        let ccw = (other.normal_bearing_at(time, units::Theta(0.))
+            - self.normal_bearing_at(time, units::Theta(0.)))
+        .rem_euclid(PI * 2.);
+        let cw = PI * 2. - ccw;
+        if ccw.min(cw) < delta.0 {
+            return Some(after);
+        }
+        let ticks =
+            self.bearing_travel_time(ccw - delta.0, cw - delta.0, other.angular_velocity_range())?;
+        lower_bound_instant(after, ticks)
+    }
+
+    /// Computes a lower bound of the time (starting from `after`) when this orbit crosses
+    /// `bearing`.
+    ///
+    /// Returns `None` if the orbit never moves towards `bearing`.
+    pub(crate) fn when_cross_bearing(
+        &self,
+        bearing: units::Bearing,
+        after: units::GameInstant,
+    ) -> Option<units::GameInstant> {
+        let time = f64::from(after.0);
+        let ccw = (bearing.0 - self.normal_bearing_at(time, units::Theta(0.))).rem_euclid(PI * 2.);
+        let ticks = self.bearing_travel_time(ccw, PI * 2. - ccw, (0., 0.))?;
+        lower_bound_instant(after, ticks)
+    }
+
+    /// Computes a lower bound of the number of ticks before the bearing of this orbit moves
+    /// either `ccw` counterclockwise or `cw` clockwise relative to a target,
+    /// where the angular velocity of the target is always within the range `target`.
+    ///
+    /// Returns `None` if the orbit can move in neither direction relative to the target.
+    fn bearing_travel_time(&self, ccw: f64, cw: f64, target: (f64, f64)) -> Option<f64> {
+        let (min, max) = self.angular_velocity_range();
+        let ccw_rate = max - target.0;
+        let cw_rate = target.1 - min;
+
+        let mut ticks = f64::INFINITY;
+        if ccw_rate > 0. {
+            ticks = ticks.min(ccw.max(0.) / ccw_rate);
+        }
+        if cw_rate > 0. {
+            ticks = ticks.min(cw.max(0.) / cw_rate);
+        }
+        if ticks.is_finite() {
+            Some(ticks)
+        } else {
+            None
+        }
+    }
+
+    /// The range of the angular velocity of the orbit about the star,
+    /// in radians per tick (positive for counterclockwise).
+    ///
+    /// The angular velocity is the highest at the periapsis and the lowest at the apoapsis
+    /// (or towards zero at infinity for escape trajectories).
+    fn angular_velocity_range(&self) -> (f64, f64) {
+        if self.is_radial() {
+            return (0., 0.);
+        }
+        let n = self.average_sweep.0 .0;
+        let e = self.eccentricity;
+        let (min, max) = match self.conic {
+            Conic::Elliptic => (
+                n * ((1. - e) / (1. + e).powi(3)).sqrt(),
+                n * ((1. + e) / (1. - e).powi(3)).sqrt(),
+            ),
+            Conic::Hyperbolic => (0., n * ((e + 1.) / (e - 1.).powi(3)).sqrt()),
+            Conic::Parabolic => (0., n * 2.),
+        };
+        match self.rotation {
+            Rotation::Counterclockwise => (min, max),
+            Rotation::Clockwise => (-max, -min),
+        }
+    }
+
+    /// Computes the mean anomaly at `time` ticks after `GameInstant::EPOCH`.
+    ///
+    /// For elliptic orbits, the mean anomaly is normalized to `[-pi, pi)`.
+    fn mean_anomaly_at(&self, time: f64) -> MeanAnomaly {
+        self.mean_anomaly_fn()(time)
+    }
+
+    /// Returns a function that computes the mean anomaly at arbitrary time
+    /// (in ticks after `GameInstant::EPOCH`), independent of the lifetime of `self`.
+    fn mean_anomaly_fn(&self) -> impl Fn(f64) -> MeanAnomaly + 'static {
+        let closed = self.conic == Conic::Elliptic;
+        let epoch = self.epoch;
+        let epoch_anomaly = self.epoch_anomaly;
+        let average_sweep = self.average_sweep;
+        move |time| {
+            let elapsed = time - f64::from(epoch.0);
+            let MeanAnomaly(ma) = epoch_anomaly + average_sweep.0 * elapsed;
+            if closed {
+                MeanAnomaly(units::Theta(normalize_angle(ma.0)))
+            } else {
+                MeanAnomaly(ma)
+            }
+        }
+    }
+
+    /// The characteristic time of the fastest motion along the orbit, in ticks.
+    ///
+    /// This is `sqrt(r_p^3 / mu)`, the time scale of the periapsis passage.
+    /// Numerical searches over time should sample at a fraction of this interval.
+    pub(crate) fn time_scale(&self) -> f64 {
+        let n = self.average_sweep.0 .0;
+        match self.conic {
+            Conic::Parabolic => 1. / 2f64.sqrt() / n,
+            _ => (self.periapsis_radius() / self.semimajor.abs()).powf(1.5) / n,
+        }
+    }
+
+    /// Rotates a vector from the perifocal frame (X axis towards periapsis) to the star frame.
+    ///
+    /// The Y axis of the perifocal frame points along the direction of motion at periapsis.
+    fn perifocal(&self, x: units::Length, y: units::Length) -> units::Displace {
+        let y = y * self.rotation.signum();
+        let (sin, cos) = self.periapsis.0.sin_cos();
+        units::Displace(Vector2::new(x * cos - y * sin, x * sin + y * cos))
+    }
+
+    /// The supremum of the magnitude of true anomaly.
+    ///
+    /// This is the true anomaly of the asymptotes for escape trajectories.
+    fn max_true_anomaly(&self) -> f64 {
+        match self.conic {
+            Conic::Elliptic => PI,
+            _ => (-1. / self.eccentricity).acos(),
+        }
+    }
+
+    /// Computes the distance from the star at the eccentric anomaly `ea`.
+    fn ea_radius(&self, ea: EccenAnomaly) -> units::Length {
+        let ea = ea.0 .0;
+        match self.conic {
+            // `1 - cos E` and `cosh H - 1` lose precision near the star
+            Conic::Elliptic if self.is_radial() => self.semimajor * (ea / 2.).sin().powi(2) * 2.,
+            Conic::Hyperbolic if self.is_radial() => {
+                -self.semimajor * (ea / 2.).sinh().powi(2) * 2.
+            }
+            Conic::Elliptic => self.semimajor * (1. - self.eccentricity * ea.cos()),
+            Conic::Hyperbolic => self.semimajor * (1. - self.eccentricity * ea.cosh()),
+            Conic::Parabolic => self.slr / 2. * (1. + ea * ea),
+        }
+    }
+
+    /// Computes the non-negative eccentric anomaly at which the orbit has distance `radius` from
+    /// the star.
+    ///
+    /// `radius` should be between the periapsis and apoapsis radii.
+    fn radius_to_ea(&self, radius: units::Length) -> EccenAnomaly {
+        let ea = match self.conic {
+            Conic::Elliptic if self.is_radial() => {
+                (radius / self.semimajor / 2.).min(1.).sqrt().asin() * 2.
+            }
+            Conic::Hyperbolic if self.is_radial() => {
+                (radius / -self.semimajor / 2.).sqrt().asinh() * 2.
+            }
+            Conic::Elliptic => ((1. - radius / self.semimajor) / self.eccentricity)
+                .clamp(-1., 1.)
+                .acos(),
+            Conic::Hyperbolic => ((1. - radius / self.semimajor) / self.eccentricity)
+                .max(1.)
+                .acosh(),
+            Conic::Parabolic => (radius * 2. / self.slr - 1.).max(0.).sqrt(),
+        };
+        EccenAnomaly(units::Theta(ea))
+    }
+
+    /// An upper bound of the rate of change of each position component with respect to the
+    /// eccentric anomaly, evaluated at `ea`.
+    fn ea_scale(&self, ea: f64) -> units::Length {
+        match self.conic {
+            Conic::Elliptic => self.semimajor,
+            Conic::Hyperbolic => -self.semimajor * self.eccentricity * ea.cosh(),
+            Conic::Parabolic => self.slr * (1. + ea.abs()),
+        }
     }
 
     /// Converts bearing to true anomaly.
     pub fn bearing_to_ta(&self, bearing: units::Bearing) -> TrueAnomaly {
-        TrueAnomaly(bearing - self.periapsis)
+        TrueAnomaly((bearing - self.periapsis) * self.rotation.signum())
+    }
+
+    /// Converts true anomaly to bearing.
+    pub fn ta_to_bearing(&self, ta: TrueAnomaly) -> units::Bearing {
+        self.periapsis + ta.0 * self.rotation.signum()
     }
 
     /// Converts true anomaly to eccentric anomaly.
     pub fn ta_to_ea(&self, ta: TrueAnomaly) -> EccenAnomaly {
         let tan_nu_2 = (ta.0 / 2.).tan();
-        let tan_e_2 = tan_nu_2 / self.te_ratio;
-        let e = tan_e_2.atan() * 2.;
+        let e = match self.conic {
+            Conic::Elliptic => (tan_nu_2 / self.te_ratio).atan() * 2.,
+            // true anomalies beyond the asymptotes are clamped to infinity
+            Conic::Hyperbolic => (tan_nu_2 / self.te_ratio).clamp(-1., 1.).atanh() * 2.,
+            Conic::Parabolic => tan_nu_2,
+        };
         EccenAnomaly(units::Theta(e))
     }
 
     /// Converts eccentric anomaly to true anomaly.
+    ///
+    /// The true anomaly of a radial trajectory is always `pi`.
     pub fn ea_to_ta(&self, ea: EccenAnomaly) -> TrueAnomaly {
-        let tan_e_2 = (ea.0 / 2.).tan();
-        let tan_nu_2 = tan_e_2 * self.te_ratio;
-        let e = tan_nu_2.atan() * 2.;
-        TrueAnomaly(units::Theta(e))
+        if self.is_radial() {
+            return TrueAnomaly(units::Theta(PI));
+        }
+        let ea = ea.0 .0;
+        let tan_nu_2 = match self.conic {
+            Conic::Elliptic => (ea / 2.).tan() * self.te_ratio,
+            Conic::Hyperbolic => (ea / 2.).tanh() * self.te_ratio,
+            Conic::Parabolic => ea,
+        };
+        TrueAnomaly(units::Theta(tan_nu_2.atan() * 2.))
     }
 
     /// Converts eccentric anomaly to mean anomaly.
     pub fn ea_to_ma(&self, ea: EccenAnomaly) -> MeanAnomaly {
-        MeanAnomaly(ea.0 - units::Theta(self.eccentricity * ea.0.sin()))
+        let e = self.eccentricity;
+        let ea = ea.0 .0;
+        let ma = match self.conic {
+            Conic::Elliptic if self.is_radial() => sine_remainder(ea, false),
+            Conic::Elliptic => ea - e * ea.sin(),
+            // the asymptotes have infinite anomalies, which would otherwise evaluate to NaN
+            Conic::Hyperbolic if ea.is_infinite() => ea,
+            Conic::Hyperbolic if self.is_radial() => sine_remainder(ea, true),
+            Conic::Hyperbolic => e * ea.sinh() - ea,
+            Conic::Parabolic => ea + ea.powi(3) / 3.,
+        };
+        MeanAnomaly(units::Theta(ma))
+    }
+
+    /// Converts mean anomaly to eccentric anomaly by solving Kepler's equation.
+    ///
+    /// The tolerance is measured on each component of the position at the resulting anomaly.
+    /// Iteration also stops when floating point precision is exhausted.
+    pub fn ma_to_ea(&self, ma: MeanAnomaly, tolerance: units::Length) -> EccenAnomaly {
+        let e = self.eccentricity;
+        let ma = ma.0 .0;
+        let tolerable = |old: f64, new: f64| {
+            let delta = (old - new).abs();
+            delta * self.ea_scale(new) <= tolerance || delta <= f64::EPSILON * new.abs().max(1.)
+        };
+
+        let ea = match self.conic {
+            Conic::Elliptic => {
+                // Danby's starter converges for the whole `0 <= e < 1` range.
+                let start = ma + 0.85 * e * ma.sin().signum();
+                util::newton_method_iterations(
+                    |ea: f64| self.ea_to_ma(EccenAnomaly(units::Theta(ea))).0 .0 - ma,
+                    |ea: f64| 1. - e * ea.cos(),
+                    start,
+                    KEPLER_ITERATIONS,
+                    tolerable,
+                )
+            }
+            Conic::Hyperbolic => {
+                // `e sinh H - H` is convex on each side of zero,
+                // so Newton's method converges monotonically after the first step.
+                let start = (ma.abs() * 2. / e + 1.8).ln().copysign(ma);
+                util::newton_method_iterations(
+                    |ea: f64| self.ea_to_ma(EccenAnomaly(units::Theta(ea))).0 .0 - ma,
+                    |ea: f64| e * ea.cosh() - 1.,
+                    start,
+                    KEPLER_ITERATIONS,
+                    tolerable,
+                )
+            }
+            Conic::Parabolic => {
+                // Barker's equation `D + D^3 / 3 = M` has a closed-form solution.
+                let w = ma.abs() * 1.5;
+                let s = (w + (w * w + 1.).sqrt()).cbrt();
+                (s - 1. / s).copysign(ma)
+            }
+        };
+        EccenAnomaly(units::Theta(ea))
     }
 }
 
-/// Represents the ECI position and velocity of an orbit at time `t`.
+/// Computes `x - sin x`, or `sinh x - x` if `hyperbolic`,
+/// without cancellation for small `x`.
+///
+/// These are the mean anomalies of radial trajectories.
+fn sine_remainder(x: f64, hyperbolic: bool) -> f64 {
+    if x.abs() > 0.5 {
+        return if hyperbolic {
+            x.sinh() - x
+        } else {
+            x - x.sin()
+        };
+    }
+    // Taylor series `x^3 / 3! -+ x^5 / 5! + x^7 / 7! -+ ...`
+    let sign = if hyperbolic { 1. } else { -1. };
+    let mut term = x.powi(3) / 6.;
+    let mut sum = term;
+    for k in 2..12 {
+        term *= sign * x * x / f64::from(k * 2 * (k * 2 + 1));
+        sum += term;
+    }
+    sum
+}
+
+/// Converts a number of ticks to a `GameDuration`, rounding to the nearest tick.
+fn round_duration(ticks: f64) -> units::GameDuration {
+    units::GameDuration(ticks.round() as u32)
+}
+
+/// Converts a number of ticks to a `GameDuration`, rounding up to the next tick.
+fn ceil_duration(ticks: f64) -> units::GameDuration {
+    units::GameDuration(ticks.ceil() as u32)
+}
+
+/// Converts a lower bound of the number of ticks after `after` to a `GameInstant`,
+/// rounding down with a safety margin.
+///
+/// Returns `None` if the instant is not representable.
+fn lower_bound_instant(after: units::GameInstant, ticks: f64) -> Option<units::GameInstant> {
+    let time = f64::from(after.0) + (ticks - CROSSING_MARGIN).max(0.).floor();
+    if time > f64::from(u32::MAX) {
+        return None;
+    }
+    Some(units::GameInstant(time as u32))
+}
+
+/// Normalizes an angle to `[-pi, pi)`.
+fn normalize_angle(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(PI * 2.) - PI
+}
+
+/// The classical elements of an orbit at a specific time.
+///
+/// Use `Orbit::from_elements` to construct the corresponding `Orbit`.
 #[derive(Debug, Clone, Copy)]
+pub struct OrbitalElements {
+    /// Mean length of the apsides, which is negative for hyperbolic orbits.
+    pub semimajor: units::Length,
+    /// Orbit eccentricity, a non-negative value.
+    pub eccentricity: f64,
+    /// Argument of periapsis.
+    pub periapsis: units::Bearing,
+    /// The direction of motion along the orbit.
+    pub rotation: Rotation,
+    /// Mean anomaly at the specified time.
+    pub mean_anomaly: units::Theta,
+}
+
+/// The error returned when `OrbitalElements` do not describe a valid orbit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementsError {
+    /// The star strength is not positive.
+    Strength,
+    /// The eccentricity is negative or not finite.
+    Eccentricity,
+    /// The eccentricity is too close to 1 to derive the orbit from the semimajor axis.
+    Parabolic,
+    /// The semimajor axis is not finite,
+    /// or its sign does not match the eccentricity.
+    Semimajor,
+    /// The argument of periapsis or the mean anomaly is not finite.
+    Angle,
+}
+
+impl fmt::Display for ElementsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            Self::Strength => "star strength must be positive",
+            Self::Eccentricity => "eccentricity must be non-negative",
+            Self::Parabolic => "parabolic orbits cannot be constructed from the semimajor axis",
+            Self::Semimajor => {
+                "semimajor axis must be positive for elliptic orbits and negative for hyperbolic orbits"
+            }
+            Self::Angle => "angles must be finite",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for ElementsError {}
+
+/// Represents the ECI position and velocity of an orbit at time `t`.
+#[derive(Debug, Clone, Copy, Getters, Setters, MutGetters, CopyGetters)]
 pub struct OrbitalState {
+    /// Position relative to the star.
+    #[getset(get_copy = "pub", set = "pub")]
     position: units::Position,
+    /// Velocity relative to the star.
+    #[getset(get_copy = "pub", set = "pub")]
     velocity: units::Velocity,
 }
 
+impl OrbitalState {
+    /// Constructs an orbital state from the position and velocity relative to the star.
+    pub fn new(position: units::Position, velocity: units::Velocity) -> Self {
+        Self { position, velocity }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct TrueAnomaly(pub units::Theta);
 
@@ -307,3 +1170,632 @@ pub struct MeanAnomaly(pub units::Theta);
 
 units::add_raw!(MeanAnomaly, units::Theta);
 units::sub_raw!(MeanAnomaly, units::Theta);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(px: f64, py: f64, vx: f64, vy: f64) -> OrbitalState {
+        OrbitalState::new(
+            units::Position(Vector2::new(px, py)),
+            units::Velocity::of(units::Displace(Vector2::new(vx, vy))),
+        )
+    }
+
+    fn specific_energy(state: OrbitalState, m: units::Mass) -> f64 {
+        state.velocity.0 .0.norm_squared() / 2. - m.0 / state.position.0.norm()
+    }
+
+    /// A deterministic xorshift generator of values in `[0, 1)`.
+    fn random(seed: &mut u64) -> f64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        (*seed >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Generates a random state at a distance of 50 to 250 from the star,
+    /// moving at `speed` times the escape velocity,
+    /// at least 0.3 radians away from the radial direction.
+    fn random_state(
+        seed: &mut u64,
+        m: units::Mass,
+        speed: f64,
+        rotation: Rotation,
+    ) -> OrbitalState {
+        let radius = 50. + random(seed) * 200.;
+        let bearing = random(seed) * PI * 2.;
+        let speed = (m.0 * 2. / radius).sqrt() * speed;
+        let heading = bearing + (0.3 + random(seed) * (PI - 0.6)) * rotation.signum();
+        state(
+            radius * bearing.cos(),
+            radius * bearing.sin(),
+            speed * heading.cos(),
+            speed * heading.sin(),
+        )
+    }
+
+    fn assert_close(actual: OrbitalState, expected: OrbitalState) {
+        let position = expected.position.0.norm();
+        let velocity = expected.velocity.0 .0.norm();
+        assert!(
+            (actual.position.0 - expected.position.0).norm() <= position * 1e-9,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+        assert!(
+            (actual.velocity.0 .0 - expected.velocity.0 .0).norm() <= velocity * 1e-8,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let m = units::Mass(1000.);
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        for &(conic, low, high) in &[
+            (Conic::Elliptic, 0.3, 0.95),
+            (Conic::Parabolic, 1., 1.),
+            (Conic::Hyperbolic, 1.05, 2.),
+        ] {
+            for &rotation in &[Rotation::Counterclockwise, Rotation::Clockwise] {
+                for _ in 0..200 {
+                    let speed = low + (high - low) * random(&mut seed);
+                    let initial = random_state(&mut seed, m, speed, rotation);
+                    let t = units::GameInstant((random(&mut seed) * 10000.) as u32);
+                    let orbit = Orbit::from_states(initial, t, m);
+                    assert_eq!(orbit.conic(), conic, "{:?}", initial);
+                    assert_eq!(orbit.rotation(), rotation, "{:?}", initial);
+                    assert_close(orbit.to_state(t, m), initial);
+
+                    // converting back at another instant preserves the trajectory
+                    let later = t + units::GameDuration(1 + (random(&mut seed) * 500.) as u32);
+                    let restored = Orbit::from_states(orbit.to_state(later, m), later, m);
+                    assert_eq!(restored.conic(), conic);
+                    assert_eq!(restored.rotation(), rotation);
+                    for &check in &[t, later, later + units::GameDuration(100)] {
+                        assert_close(restored.to_state(check, m), orbit.to_state(check, m));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn radius_crossing_lower_bound() {
+        let m = units::Mass(1000.);
+        let mut seed = 0x9e37_79b9_7f4a_7c15;
+        let mut crossings = 0;
+        for case in 0..600 {
+            let rotation = if case % 2 == 0 {
+                Rotation::Counterclockwise
+            } else {
+                Rotation::Clockwise
+            };
+            let speed = [0.5 + random(&mut seed) * 0.45, 1., 1.05 + random(&mut seed)][case % 3];
+            let epoch = units::GameInstant(1000);
+            let orbit = Orbit::from_states(random_state(&mut seed, m, speed, rotation), epoch, m);
+            let radius = 20. + random(&mut seed) * 400.;
+            let after = epoch + units::GameDuration((random(&mut seed) * 2000.) as u32);
+
+            let outside = orbit.radius_comparator(radius);
+            let initial = outside(after);
+            match orbit.when_intersect_radius(radius, after) {
+                Some(bound) => {
+                    crossings += 1;
+                    assert!(bound >= after);
+                    for tick in after.0..=bound.0 {
+                        assert_eq!(
+                            outside(units::GameInstant(tick)),
+                            initial,
+                            "{:?} crosses {} at {} before {:?}",
+                            orbit,
+                            radius,
+                            tick,
+                            bound
+                        );
+                    }
+                    // the bound is tight: the crossing happens within the next tick
+                    assert_ne!(outside(bound + units::GameDuration(2)), initial);
+                }
+                None => {
+                    for tick in after.0..after.0 + 5000 {
+                        assert_eq!(outside(units::GameInstant(tick)), initial);
+                    }
+                }
+            }
+        }
+        assert!(crossings > 100);
+    }
+
+    #[test]
+    fn comparisons_form_total_orders() {
+        let m = units::Mass(1000.);
+        let epoch = units::GameInstant(0);
+        let mut seed = 0xd1b5_4a32_d192_ed03;
+        let mut orbits: Vec<Orbit> = (0..120)
+            .map(|i| {
+                let rotation = if i % 2 == 0 {
+                    Rotation::Counterclockwise
+                } else {
+                    Rotation::Clockwise
+                };
+                let speed = 0.4 + random(&mut seed) * 0.8;
+                Orbit::from_states(random_state(&mut seed, m, speed, rotation), epoch, m)
+            })
+            .collect();
+        // orbits on both sides of the zero bearing
+        for &(bearing, rotation) in &[
+            (1e-9, Rotation::Counterclockwise),
+            (-1e-9, Rotation::Counterclockwise),
+            (1e-9, Rotation::Clockwise),
+            (-1e-9, Rotation::Clockwise),
+        ] {
+            let (sin, cos) = f64::sin_cos(bearing);
+            let speed = rotation.signum() * 3.;
+            orbits.push(Orbit::from_states(
+                state(100. * cos, 100. * sin, -speed * sin, speed * cos),
+                epoch,
+                m,
+            ));
+        }
+        orbits.push(orbits[7].clone());
+
+        for &t in &[epoch, units::GameInstant(137), units::GameInstant(4000)] {
+            let time = f64::from(t.0);
+            for &tolerance in &[0., 1e-3] {
+                let radius = |a: &Orbit, b: &Orbit| a.compare_radius(b, t, tolerance);
+                let bearing =
+                    |a: &Orbit, b: &Orbit| a.compare_bearing(b, t, units::Theta(tolerance));
+                for compare in &[&radius as &dyn Fn(&Orbit, &Orbit) -> Ordering, &bearing] {
+                    assert_eq!(
+                        compare(&orbits[7], &orbits[orbits.len() - 1]),
+                        Ordering::Equal
+                    );
+                    for a in &orbits {
+                        assert_eq!(compare(a, a), Ordering::Equal);
+                        for b in &orbits {
+                            assert_eq!(compare(a, b), compare(b, a).reverse());
+                        }
+                    }
+                    for _ in 0..20000 {
+                        let mut pick =
+                            || &orbits[(random(&mut seed) * orbits.len() as f64) as usize];
+                        let (a, b, c) = (pick(), pick(), pick());
+                        if compare(a, b) != Ordering::Greater && compare(b, c) != Ordering::Greater
+                        {
+                            assert_ne!(compare(a, c), Ordering::Greater);
+                        }
+                    }
+
+                    // sorting is consistent with every pairwise comparison
+                    let mut sorted = orbits.clone();
+                    sorted.sort_by(|a, b| compare(a, b));
+                    for (i, a) in sorted.iter().enumerate() {
+                        for b in &sorted[i + 1..] {
+                            assert_ne!(compare(a, b), Ordering::Greater);
+                        }
+                    }
+                }
+
+                // sorted bearings increase from the zero bearing, wrapping at `2pi`
+                let mut sorted = orbits.clone();
+                sorted.sort_by(|a, b| bearing(a, b));
+                let bearings: Vec<f64> = sorted
+                    .iter()
+                    .map(|orbit| orbit.normal_bearing_at(time, units::Theta(0.)))
+                    .collect();
+                assert!(bearings.iter().all(|&b| (0. ..PI * 2.).contains(&b)));
+                assert!(bearings
+                    .windows(2)
+                    .all(|pair| pair[0] <= pair[1] + tolerance * 2.));
+            }
+        }
+
+        // a bearing slightly clockwise of zero is greater than one slightly counterclockwise
+        let n = orbits.len();
+        let tolerance = units::Theta(0.);
+        assert_eq!(
+            orbits[n - 4].compare_bearing(&orbits[n - 5], epoch, tolerance),
+            Ordering::Greater
+        );
+        assert_eq!(
+            orbits[n - 2].compare_bearing(&orbits[n - 3], epoch, tolerance),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn radial_trajectories() {
+        let m = units::Mass(1000.);
+        let t = units::GameInstant(100);
+        for &(px, py, vx, vy) in &[
+            (100., 0., 0., 0.),
+            (100., 0., 1., 0.),
+            (100., 0., -2., 0.),
+            (100., 0., 10., 0.),
+            (60., -80., 3., -4.),
+            (60., -80., -0.3, 0.4),
+            // exactly the escape velocity
+            (2000., 0., 1., 0.),
+        ] {
+            let initial = state(px, py, vx, vy);
+            let orbit = Orbit::from_states(initial, t, m);
+            assert!(orbit.is_radial(), "{:?}", orbit);
+
+            let restored = orbit.to_state(t, m);
+            assert!(
+                (restored.position.0 - initial.position.0).norm() < 1e-9,
+                "{:?} {:?} {:?}",
+                restored,
+                initial,
+                orbit
+            );
+            assert!((restored.velocity.0 .0 - initial.velocity.0 .0).norm() < 1e-6);
+
+            let direction = initial.position.0.normalize();
+            for &ticks in &[1, 5, 20, 100] {
+                let later = orbit.to_state(t + units::GameDuration(ticks), m);
+                let position = later.position.0;
+                assert!(position.iter().all(|x| x.is_finite()), "{:?}", orbit);
+                assert!(util::cross2d(direction, position).abs() < 1e-6);
+                assert!(position.dot(&direction) >= 0.);
+                let energy = specific_energy(later, m);
+                assert!((energy - specific_energy(initial, m)).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn radial_fall() {
+        let m = units::Mass(1000.);
+        let t = units::GameInstant(0);
+        let orbit = Orbit::from_states(state(0., 100., 0., 0.), t, m);
+        assert_eq!(orbit.conic(), Conic::Elliptic);
+        assert!((orbit.apoapsis_radius().expect("bound trajectory") - 100.).abs() < 1e-9);
+
+        // the body falls straight towards the star, accelerating all the way
+        let mut radius = 100.;
+        let mut speed = 0.;
+        for tick in 1..20 {
+            let state = orbit.to_state(units::GameInstant(tick), m);
+            let new_radius = state.position.0.norm();
+            let new_speed = state.velocity.0 .0.norm();
+            assert!(new_radius < radius && new_speed > speed);
+            assert!(state.position.0[0].abs() < 1e-9);
+            radius = new_radius;
+            speed = new_speed;
+        }
+        assert!(!orbit
+            .bearing_in_range(units::Bearing(-1.), units::Bearing(1.))(
+            t
+        ));
+        assert!(orbit
+            .bearing_in_range(units::Bearing(1.), units::Bearing(2.))(
+            t
+        ));
+        assert_eq!(
+            orbit.when_intersect_bearing(&orbit, units::Theta(0.1), t),
+            Some(t)
+        );
+    }
+
+    #[test]
+    fn clockwise_bearings() {
+        let m = units::Mass(1000.);
+        let epoch = units::GameInstant(0);
+        let mut seed = 0x853c_49e6_748f_ea9b;
+        let bearing_of = |state: OrbitalState| {
+            let position = state.position.0;
+            position[1].atan2(position[0]).rem_euclid(PI * 2.)
+        };
+        let orbits: Vec<Orbit> = (0..60)
+            .map(|i| {
+                let speed = [0.4, 0.7, 0.95, 1.3][i % 4] + random(&mut seed) * 0.04;
+                Orbit::from_states(
+                    random_state(&mut seed, m, speed, Rotation::Clockwise),
+                    epoch,
+                    m,
+                )
+            })
+            .collect();
+        assert!(orbits
+            .iter()
+            .all(|orbit| orbit.rotation() == Rotation::Clockwise));
+
+        let mut checked = 0;
+        for _ in 0..200 {
+            let low = random(&mut seed) * PI * 4. - PI * 2.;
+            let arc = random(&mut seed) * PI * 2.;
+            let ranges: Vec<_> = orbits
+                .iter()
+                .map(|orbit| orbit.bearing_in_range(units::Bearing(low), units::Bearing(low + arc)))
+                .collect();
+            for tick in (0..3000).step_by(37) {
+                let t = units::GameInstant(tick);
+                for (orbit, in_range) in orbits.iter().zip(&ranges) {
+                    let offset = (bearing_of(orbit.to_state(t, m)) - low).rem_euclid(PI * 2.);
+                    if (offset - arc).abs() < 1e-9 || !(1e-9..PI * 2. - 1e-9).contains(&offset) {
+                        continue; // too close to the ends of the arc
+                    }
+                    assert_eq!(in_range(t), offset < arc, "{:?} at {:?}", orbit, t);
+                    checked += 1;
+                }
+            }
+        }
+        assert!(checked > 100000);
+
+        for tick in (0..3000).step_by(101) {
+            let t = units::GameInstant(tick);
+            for a in &orbits {
+                for b in &orbits {
+                    let (bearing_a, bearing_b) =
+                        (bearing_of(a.to_state(t, m)), bearing_of(b.to_state(t, m)));
+                    if (bearing_a - bearing_b).abs() < 1e-9 {
+                        continue;
+                    }
+                    assert_eq!(
+                        a.compare_bearing(b, t, units::Theta(0.)),
+                        bearing_a.total_cmp(&bearing_b)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn near_parabolic_conservation() {
+        let m = units::Mass(1000.);
+        let epoch = units::GameInstant(1_000_000_000);
+        for &(semimajor, eccentricity) in &[(1000., 0.9999), (-1000., 1.0001)] {
+            for &rotation in &[Rotation::Counterclockwise, Rotation::Clockwise] {
+                let elements = OrbitalElements {
+                    semimajor,
+                    eccentricity,
+                    periapsis: units::Bearing(1.),
+                    rotation,
+                    mean_anomaly: units::Theta(-0.5),
+                };
+                let orbit = Orbit::from_elements(elements, epoch, m).expect("valid elements");
+                let energy = orbit.specific_energy(m);
+                let momentum = orbit.angular_momentum(m);
+
+                let passage = epoch
+                    + orbit
+                        .time_to_periapsis(epoch)
+                        .expect("approaching the periapsis");
+                let ticks =
+                    (0..7000)
+                        .step_by(7)
+                        .map(|tick| epoch + units::GameDuration(tick))
+                        .chain((0..20).map(|tick| {
+                            passage + units::GameDuration(tick) - units::GameDuration(10)
+                        }));
+                for t in ticks {
+                    let state = orbit.to_state(t, m);
+                    let (r, v) = (state.position.0, state.velocity.0 .0);
+                    assert!(r.iter().chain(v.iter()).all(|x| x.is_finite()));
+                    let scale = m.0 / r.norm();
+                    assert!(
+                        (specific_energy(state, m) - energy).abs() <= scale * 1e-9,
+                        "energy drifts at {:?}: {} != {}",
+                        t,
+                        specific_energy(state, m),
+                        energy
+                    );
+                    assert!(
+                        (util::cross2d(r, v) - momentum).abs() <= r.norm() * v.norm() * 1e-9,
+                        "angular momentum drifts at {:?}",
+                        t
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn derived_quantities() {
+        let m = units::Mass(1000.);
+        let t = units::GameInstant(500);
+        for &rotation in &[Rotation::Counterclockwise, Rotation::Clockwise] {
+            let (a, e) = (200., 0.6);
+            let elements = OrbitalElements {
+                semimajor: a,
+                eccentricity: e,
+                periapsis: units::Bearing(2.),
+                rotation,
+                mean_anomaly: units::Theta(1.),
+            };
+            let orbit = Orbit::from_elements(elements, t, m).expect("valid elements");
+            let sweep = (m.0 / a.powi(3)).sqrt();
+            assert!((orbit.periapsis_radius() - a * (1. - e)).abs() < 1e-9);
+            assert!((orbit.apoapsis_radius().expect("elliptic") - a * (1. + e)).abs() < 1e-9);
+            assert!((orbit.semi_latus_rectum() - a * (1. - e * e)).abs() < 1e-9);
+            let period = PI * 2. / sweep;
+            assert_eq!(
+                orbit.period(),
+                Some(units::GameDuration(period.round() as u32))
+            );
+            assert!((orbit.specific_energy(m) + m.0 / 2. / a).abs() < 1e-12);
+            let momentum = (m.0 * a * (1. - e * e)).sqrt() * rotation.signum();
+            assert!((orbit.angular_momentum(m) - momentum).abs() < 1e-9);
+
+            // the quantities agree with the state vectors
+            let state = orbit.to_state(t, m);
+            assert!((specific_energy(state, m) - orbit.specific_energy(m)).abs() < 1e-9);
+            let cross = util::cross2d(state.position.0, state.velocity.0 .0);
+            assert!((cross - orbit.angular_momentum(m)).abs() < 1e-6);
+
+            // the apsides are reached within one tick after the computed durations
+            let to_periapsis = orbit.time_to_periapsis(t).expect("elliptic");
+            assert_eq!(to_periapsis.0, ((PI * 2. - 1.) / sweep).ceil() as u32);
+            let to_apoapsis = orbit.time_to_apoapsis(t).expect("elliptic");
+            assert_eq!(to_apoapsis.0, ((PI - 1.) / sweep).ceil() as u32);
+            for &(duration, radius) in &[
+                (to_periapsis, orbit.periapsis_radius()),
+                (to_apoapsis, orbit.apoapsis_radius().expect("elliptic")),
+            ] {
+                let at = |ticks: u32| {
+                    let instant = t + units::GameDuration(ticks);
+                    (orbit.to_state(instant, m).position.0.norm() - radius).abs()
+                };
+                let step = orbit.to_state(t, m).velocity.0 .0.norm().max(1.);
+                assert!(at(duration.0) < step * 2.);
+                assert!(at(duration.0 - 1) < step * 2.);
+            }
+        }
+
+        let elements = OrbitalElements {
+            semimajor: -200.,
+            eccentricity: 1.5,
+            periapsis: units::Bearing(0.),
+            rotation: Rotation::Counterclockwise,
+            mean_anomaly: units::Theta(-1.),
+        };
+        let orbit = Orbit::from_elements(elements, t, m).expect("valid elements");
+        assert!((orbit.periapsis_radius() - 100.).abs() < 1e-9);
+        assert_eq!(orbit.apoapsis_radius(), None);
+        assert_eq!(orbit.period(), None);
+        assert_eq!(orbit.time_to_apoapsis(t), None);
+        assert!((orbit.specific_energy(m) - m.0 / 400.).abs() < 1e-12);
+        let sweep = (m.0 / 200f64.powi(3)).sqrt();
+        let to_periapsis = orbit
+            .time_to_periapsis(t)
+            .expect("approaching the periapsis");
+        assert_eq!(to_periapsis.0, (1. / sweep).ceil() as u32);
+        let after = t + to_periapsis + units::GameDuration(1);
+        assert_eq!(orbit.time_to_periapsis(after), None);
+    }
+
+    #[test]
+    fn elements_errors() {
+        let m = units::Mass(1000.);
+        let t = units::GameInstant(0);
+        let valid = OrbitalElements {
+            semimajor: 100.,
+            eccentricity: 0.5,
+            periapsis: units::Bearing(0.),
+            rotation: Rotation::Counterclockwise,
+            mean_anomaly: units::Theta(0.),
+        };
+        assert!(Orbit::from_elements(valid, t, m).is_ok());
+
+        let error = |elements: OrbitalElements, m: f64| {
+            Orbit::from_elements(elements, t, units::Mass(m)).expect_err("invalid elements")
+        };
+        assert_eq!(error(valid, 0.), ElementsError::Strength);
+        assert_eq!(error(valid, f64::NAN), ElementsError::Strength);
+        for &eccentricity in &[-0.1, f64::INFINITY, f64::NAN] {
+            let elements = OrbitalElements {
+                eccentricity,
+                ..valid
+            };
+            assert_eq!(error(elements, m.0), ElementsError::Eccentricity);
+        }
+        let elements = OrbitalElements {
+            eccentricity: 1.,
+            ..valid
+        };
+        assert_eq!(error(elements, m.0), ElementsError::Parabolic);
+        for &(semimajor, eccentricity) in &[
+            (-100., 0.5),
+            (0., 0.5),
+            (f64::INFINITY, 0.5),
+            (100., 1.5),
+            (f64::NEG_INFINITY, 1.5),
+        ] {
+            let elements = OrbitalElements {
+                semimajor,
+                eccentricity,
+                ..valid
+            };
+            assert_eq!(error(elements, m.0), ElementsError::Semimajor);
+        }
+        let elements = OrbitalElements {
+            periapsis: units::Bearing(f64::NAN),
+            ..valid
+        };
+        assert_eq!(error(elements, m.0), ElementsError::Angle);
+        let elements = OrbitalElements {
+            mean_anomaly: units::Theta(f64::INFINITY),
+            ..valid
+        };
+        assert_eq!(error(elements, m.0), ElementsError::Angle);
+    }
+
+    #[test]
+    fn elements_round_trip() {
+        let m = units::Mass(1000.);
+        let t = units::GameInstant(300);
+        let mut seed = 0x5851_f42d_4c95_7f2d;
+        for case in 0..400 {
+            let hyperbolic = case % 2 == 1;
+            let eccentricity = if hyperbolic {
+                1.05 + random(&mut seed) * 2.
+            } else {
+                random(&mut seed) * 0.95
+            };
+            let semimajor = (50. + random(&mut seed) * 200.) * if hyperbolic { -1. } else { 1. };
+            let rotation = if case % 4 < 2 {
+                Rotation::Counterclockwise
+            } else {
+                Rotation::Clockwise
+            };
+            let elements = OrbitalElements {
+                semimajor,
+                eccentricity,
+                periapsis: units::Bearing(random(&mut seed) * PI * 2.),
+                rotation,
+                mean_anomaly: units::Theta(random(&mut seed) * 4. - 2.),
+            };
+            let orbit = Orbit::from_elements(elements, t, m).expect("valid elements");
+            let restored = Orbit::from_states(orbit.to_state(t, m), t, m);
+            assert_eq!(restored.conic(), orbit.conic());
+            assert_eq!(restored.rotation(), rotation);
+            assert!((restored.semimajor - semimajor).abs() <= semimajor.abs() * 1e-9);
+            assert!((restored.eccentricity - eccentricity).abs() < 1e-9);
+            if eccentricity > 1e-6 {
+                let periapsis = (restored.periapsis - elements.periapsis).0;
+                assert!(normalize_angle(periapsis).abs() < 1e-6, "{:?}", elements);
+            }
+            let later = t + units::GameDuration(97);
+            assert_close(restored.to_state(later, m), orbit.to_state(later, m));
+        }
+    }
+
+    #[test]
+    fn impulse_escape() {
+        let m = units::Mass(1000.);
+        let t = units::GameInstant(40);
+        let orbit = Orbit::from_states(
+            state(100., 0., 0., (m.0 / 100f64).sqrt()),
+            units::GameInstant(0),
+            m,
+        );
+        assert_eq!(orbit.conic(), Conic::Elliptic);
+
+        let before = orbit.to_state(t, m);
+        let v = before.velocity.0 .0;
+        // a prograde burn to 1.5 times the circular speed exceeds the escape speed
+        let delta_v = v * 0.5;
+        let burnt = orbit.apply_impulse(t, units::Velocity::of(units::Displace(delta_v)), m);
+        assert_eq!(burnt.conic(), Conic::Hyperbolic);
+        assert_eq!(burnt.rotation(), Rotation::Counterclockwise);
+
+        let after = burnt.to_state(t, m);
+        assert!((after.position.0 - before.position.0).norm() < 1e-9);
+        assert!((after.velocity.0 .0 - (v + delta_v)).norm() < 1e-9);
+        let expected = v.dot(&delta_v) + delta_v.norm_squared() / 2.;
+        let change = burnt.specific_energy(m) - orbit.specific_energy(m);
+        assert!(
+            (change - expected).abs() < 1e-9,
+            "{} != {}",
+            change,
+            expected
+        );
+        assert!(burnt.specific_energy(m) > 0.);
+    }
+}
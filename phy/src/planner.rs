@@ -0,0 +1,449 @@
+//! Plans impulsive manoeuvres between orbits around the same star.
+//!
+//! All planners assume coplanar orbits and instantaneous burns.
+//! Transfers between two orbits treat the orbits as circular,
+//! using the semimajor axis of the target orbit as its radius.
+
+use std::f64::consts::PI;
+
+use getset::*;
+use nalgebra::Vector2;
+
+use super::{Conic, Orbit, Rotation};
+
+/// Orbits with eccentricity below this value are regarded as circular,
+/// so burns on them do not wait for an apsis.
+const CIRCULAR_TOLERANCE: f64 = 1e-6;
+
+/// An instantaneous burn in a manoeuvre plan.
+#[derive(Debug, Clone, Copy, Getters, CopyGetters)]
+pub struct Node {
+    /// The instant at which the burn is performed.
+    #[getset(get_copy = "pub")]
+    at: units::GameInstant,
+    /// The velocity change applied by the burn.
+    #[getset(get_copy = "pub")]
+    delta_v: units::Velocity,
+}
+
+/// A sequence of burns together with the predicted orbits between them.
+#[derive(Debug, Clone, Getters)]
+pub struct Plan {
+    /// The burns in chronological order.
+    #[getset(get = "pub")]
+    nodes: Vec<Node>,
+    /// The predicted orbits after each burn.
+    ///
+    /// `orbits[i]` is the orbit immediately after `nodes[i]`.
+    #[getset(get = "pub")]
+    orbits: Vec<Orbit>,
+}
+
+impl Plan {
+    fn new() -> Self {
+        Self {
+            nodes: vec![],
+            orbits: vec![],
+        }
+    }
+
+    /// Appends a burn to the plan, predicting the orbit after the burn from `orbit`.
+    fn burn(
+        &mut self,
+        orbit: &Orbit,
+        at: units::GameInstant,
+        delta_v: units::Velocity,
+        m: units::Mass,
+    ) -> &Orbit {
+        self.nodes.push(Node { at, delta_v });
+        self.orbits.push(orbit.apply_impulse(at, delta_v, m));
+        self.orbits.last().expect("just pushed")
+    }
+
+    /// The sum of the magnitudes of all burns.
+    pub fn total_delta_v(&self) -> units::Speed {
+        units::Rate(self.nodes.iter().map(|node| node.delta_v.0 .0.norm()).sum())
+    }
+
+    /// The time from the first burn to the last burn.
+    pub fn time_of_flight(&self) -> units::GameDuration {
+        match (self.nodes.first(), self.nodes.last()) {
+            (Some(first), Some(last)) => last.at - first.at,
+            _ => units::GameDuration::default(),
+        }
+    }
+
+    /// The predicted orbit after the whole plan is executed.
+    pub fn final_orbit(&self) -> Option<&Orbit> {
+        self.orbits.last()
+    }
+}
+
+/// Plans a single burn at `at` that makes the orbit circular at the current radius.
+///
+/// The circular orbit keeps the direction of motion of `orbit`.
+/// For the cheapest burn, `at` should be an apsis of `orbit`.
+pub fn circularize(orbit: &Orbit, at: units::GameInstant, m: units::Mass) -> Plan {
+    let mut plan = Plan::new();
+    let delta_v = circularize_delta_v(orbit, at, m);
+    let _ = plan.burn(orbit, at, delta_v, m);
+    plan
+}
+
+/// Plans a Hohmann transfer from `from` to the circular orbit at the radius of `to`.
+///
+/// The first burn is performed at the next apsis of `from` after `after`
+/// (or at `after` if `from` is circular).
+///
+/// Returns `None` if either orbit is an escape trajectory.
+pub fn hohmann(
+    from: &Orbit,
+    to: &Orbit,
+    after: units::GameInstant,
+    m: units::Mass,
+) -> Option<Plan> {
+    let target = target_radius(from, to)?;
+
+    let mut plan = Plan::new();
+    let depart = departure(from, target, after)?;
+    let radius = from.approx_position(depart, 0.).0.norm();
+    let transfer = plan.burn(
+        from,
+        depart,
+        tangential_delta_v(from, depart, (radius + target) / 2., m),
+        m,
+    );
+
+    let arrive = depart + opposite_apsis(transfer, depart, target > radius)?;
+    let delta_v = circularize_delta_v(transfer, arrive, m);
+    let transfer = transfer.clone();
+    let _ = plan.burn(&transfer, arrive, delta_v, m);
+    Some(plan)
+}
+
+/// Plans a bi-elliptic transfer from `from` to the circular orbit at the radius of `to`,
+/// passing through the apoapsis at `intermediate`.
+///
+/// The first burn is performed at the next apsis of `from` after `after`
+/// (or at `after` if `from` is circular).
+///
+/// Returns `None` if either orbit is an escape trajectory,
+/// or if `intermediate` is below either orbit.
+pub fn bi_elliptic(
+    from: &Orbit,
+    to: &Orbit,
+    intermediate: units::Length,
+    after: units::GameInstant,
+    m: units::Mass,
+) -> Option<Plan> {
+    let target = target_radius(from, to)?;
+
+    let mut plan = Plan::new();
+    let depart = departure(from, intermediate, after)?;
+    let radius = from.approx_position(depart, 0.).0.norm();
+    if intermediate < radius || intermediate < target {
+        return None;
+    }
+
+    let first = plan.burn(
+        from,
+        depart,
+        tangential_delta_v(from, depart, (radius + intermediate) / 2., m),
+        m,
+    );
+    let turn = depart + opposite_apsis(first, depart, true)?;
+
+    let first = first.clone();
+    let second = plan.burn(
+        &first,
+        turn,
+        tangential_delta_v(&first, turn, (intermediate + target) / 2., m),
+        m,
+    );
+    let arrive = turn + opposite_apsis(second, turn, false)?;
+
+    let delta_v = circularize_delta_v(second, arrive, m);
+    let second = second.clone();
+    let _ = plan.burn(&second, arrive, delta_v, m);
+    Some(plan)
+}
+
+/// Plans a phasing manoeuvre to rendezvous with `target`,
+/// which is in the same circular orbit as `chaser`.
+///
+/// The chaser burns at `after` into a phasing orbit,
+/// completes `revolutions` revolutions on it,
+/// and then matches the velocity of the target.
+///
+/// Returns `None` if either orbit is an escape trajectory, if `revolutions` is zero,
+/// or if the orbits move in different directions.
+pub fn phasing(
+    chaser: &Orbit,
+    target: &Orbit,
+    revolutions: u32,
+    after: units::GameInstant,
+    m: units::Mass,
+) -> Option<Plan> {
+    if chaser.conic() != Conic::Elliptic
+        || target.conic() != Conic::Elliptic
+        || chaser.rotation() != target.rotation()
+        || revolutions == 0
+    {
+        return None;
+    }
+
+    // the phase angle of the target ahead of the chaser along the direction of motion
+    let chaser_bearing = chaser.approx_bearing(after, m, units::Theta(0.));
+    let target_bearing = target.approx_bearing(after, m, units::Theta(0.));
+    let phase = ((target_bearing - chaser_bearing).0 * chaser.rotation().signum() + PI)
+        .rem_euclid(PI * 2.)
+        - PI;
+
+    // After `k` phasing revolutions, the target must have travelled `2 pi k - phase`.
+    let target_period = PI * 2. / target.average_sweep().0 .0;
+    let phasing_period = target_period * (1. - phase / (PI * 2. * f64::from(revolutions)));
+    let semimajor = (m.0 * (phasing_period / PI / 2.).powi(2)).cbrt();
+
+    let mut plan = Plan::new();
+    let phasing = plan.burn(
+        chaser,
+        after,
+        tangential_delta_v(chaser, after, semimajor, m),
+        m,
+    );
+    if phasing.conic() != Conic::Elliptic {
+        return None;
+    }
+    let duration = PI * 2. / phasing.average_sweep().0 .0 * f64::from(revolutions);
+    let arrive = after + units::GameDuration(duration.round() as u32);
+
+    let delta_v = target.approx_velocity(arrive, m, 0.) - phasing.approx_velocity(arrive, m, 0.);
+    let phasing = phasing.clone();
+    let _ = plan.burn(&phasing, arrive, delta_v, m);
+    Some(plan)
+}
+
+/// Returns the radius of the circular orbit approximating `to`,
+/// or `None` if either orbit is not elliptic.
+fn target_radius(from: &Orbit, to: &Orbit) -> Option<units::Length> {
+    if from.conic() == Conic::Elliptic && to.conic() == Conic::Elliptic {
+        Some(to.semimajor())
+    } else {
+        None
+    }
+}
+
+/// Selects the departure instant for a transfer towards `radius`.
+///
+/// Raising the orbit departs from the periapsis, and lowering the orbit departs from the apoapsis.
+fn departure(
+    from: &Orbit,
+    radius: units::Length,
+    after: units::GameInstant,
+) -> Option<units::GameInstant> {
+    if from.eccentricity() < CIRCULAR_TOLERANCE {
+        return Some(after);
+    }
+    let wait = if radius > from.semimajor() {
+        from.time_to_periapsis(after)
+    } else {
+        from.time_to_apoapsis(after)
+    };
+    Some(after + wait?)
+}
+
+/// Computes the time from `at` (which is at an apsis of `orbit`) to the opposite apsis.
+fn opposite_apsis(
+    orbit: &Orbit,
+    at: units::GameInstant,
+    raising: bool,
+) -> Option<units::GameDuration> {
+    if raising {
+        orbit.time_to_apoapsis(at)
+    } else {
+        orbit.time_to_periapsis(at)
+    }
+}
+
+/// Computes the burn along the direction of motion at `at`
+/// that changes the semimajor axis of `orbit` to `semimajor`.
+fn tangential_delta_v(
+    orbit: &Orbit,
+    at: units::GameInstant,
+    semimajor: units::Length,
+    m: units::Mass,
+) -> units::Velocity {
+    let radius = orbit.approx_position(at, 0.).0.norm();
+    let velocity = orbit.approx_velocity(at, m, 0.);
+    let speed = velocity.0 .0.norm();
+
+    // vis-viva equation
+    let target_speed = (m.0 * (2. / radius - 1. / semimajor)).sqrt();
+    velocity * ((target_speed - speed) / speed)
+}
+
+/// Computes the burn at `at` that makes `orbit` circular at the current radius.
+fn circularize_delta_v(orbit: &Orbit, at: units::GameInstant, m: units::Mass) -> units::Velocity {
+    let position = orbit.approx_position(at, 0.).0;
+    let radius = position.norm();
+    let speed = (m.0 / radius).sqrt();
+
+    let tangent = match orbit.rotation() {
+        Rotation::Counterclockwise => Vector2::new(-position[1], position[0]),
+        Rotation::Clockwise => Vector2::new(position[1], -position[0]),
+    };
+    let circular = units::Velocity::of(units::Displace(tangent * (speed / radius)));
+    circular - orbit.approx_velocity(at, m, 0.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OrbitalState;
+
+    const M: units::Mass = units::Mass(1e4);
+
+    /// Creates an orbit at bearing `bearing` and radius `radius` at instant 0,
+    /// moving perpendicular to the radius at `speed` times the circular speed.
+    fn orbit(radius: f64, bearing: f64, speed: f64, rotation: Rotation) -> Orbit {
+        let speed = (M.0 / radius).sqrt() * speed * rotation.signum();
+        let (sin, cos) = bearing.sin_cos();
+        let state = OrbitalState::new(
+            units::Position(Vector2::new(radius * cos, radius * sin)),
+            units::Velocity::of(units::Displace(Vector2::new(-speed * sin, speed * cos))),
+        );
+        Orbit::from_states(state, units::GameInstant(0), M)
+    }
+
+    fn assert_relative(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * tolerance,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    fn circular_speed(radius: f64) -> f64 {
+        (M.0 / radius).sqrt()
+    }
+
+    /// The speed at `radius` on an orbit with semimajor axis `semimajor`.
+    fn vis_viva(radius: f64, semimajor: f64) -> f64 {
+        (M.0 * (2. / radius - 1. / semimajor)).sqrt()
+    }
+
+    #[test]
+    fn circularize_at_apoapsis() {
+        for &rotation in &[Rotation::Counterclockwise, Rotation::Clockwise] {
+            let from = orbit(10000., 0.5, 1.2, rotation);
+            let apoapsis = from.apoapsis_radius().expect("elliptic");
+            let at = units::GameInstant(0) + from.time_to_apoapsis(units::GameInstant(0)).unwrap();
+
+            let plan = circularize(&from, at, M);
+            assert_eq!(plan.nodes().len(), 1);
+            let circular = plan.final_orbit().unwrap();
+            assert!(
+                circular.eccentricity() < 1e-9,
+                "{}",
+                circular.eccentricity()
+            );
+            assert_eq!(circular.rotation(), rotation);
+            assert_relative(circular.semimajor(), apoapsis, 1e-6);
+
+            let expected = circular_speed(apoapsis) - vis_viva(apoapsis, from.semimajor());
+            assert_relative(plan.total_delta_v().0, expected, 1e-4);
+        }
+    }
+
+    #[test]
+    fn hohmann_transfer() {
+        for &(r1, r2) in &[(10000., 30000.), (30000., 10000.)] {
+            for &rotation in &[Rotation::Counterclockwise, Rotation::Clockwise] {
+                let from = orbit(r1, 1., 1., rotation);
+                let to = orbit(r2, 2., 1., rotation);
+                let after = units::GameInstant(100);
+
+                let plan = hohmann(&from, &to, after, M).expect("elliptic orbits");
+                assert_eq!(plan.nodes().len(), 2);
+                assert_eq!(plan.nodes()[0].at(), after);
+
+                let target = plan.final_orbit().unwrap();
+                assert_relative(target.semimajor(), r2, 1e-6);
+                assert!(target.eccentricity() < 1e-6, "{}", target.eccentricity());
+                assert_eq!(target.rotation(), rotation);
+
+                let transfer = (r1 + r2) / 2.;
+                let expected = (vis_viva(r1, transfer) - circular_speed(r1)).abs()
+                    + (circular_speed(r2) - vis_viva(r2, transfer)).abs();
+                assert_relative(plan.total_delta_v().0, expected, 1e-6);
+
+                let half_period = PI * (transfer.powi(3) / M.0).sqrt();
+                assert!((f64::from(plan.time_of_flight().0) - half_period).abs() <= 1.);
+            }
+        }
+    }
+
+    #[test]
+    fn bi_elliptic_transfer() {
+        let (r1, r2, rb) = (10000., 30000., 60000.);
+        let from = orbit(r1, 0., 1., Rotation::Counterclockwise);
+        let to = orbit(r2, 0., 1., Rotation::Counterclockwise);
+
+        assert!(bi_elliptic(&from, &to, r2 / 2., units::GameInstant(0), M).is_none());
+
+        let plan = bi_elliptic(&from, &to, rb, units::GameInstant(0), M).expect("valid transfer");
+        assert_eq!(plan.nodes().len(), 3);
+
+        let target = plan.final_orbit().unwrap();
+        assert_relative(target.semimajor(), r2, 1e-6);
+        assert!(target.eccentricity() < 1e-6, "{}", target.eccentricity());
+
+        let (first, second) = ((r1 + rb) / 2., (r2 + rb) / 2.);
+        let expected = (vis_viva(r1, first) - circular_speed(r1))
+            + (vis_viva(rb, second) - vis_viva(rb, first))
+            + (vis_viva(r2, second) - circular_speed(r2));
+        assert_relative(plan.total_delta_v().0, expected, 1e-6);
+    }
+
+    #[test]
+    fn phasing_rendezvous() {
+        let radius = 10000.;
+        for &rotation in &[Rotation::Counterclockwise, Rotation::Clockwise] {
+            for &(phase, revolutions) in &[(1., 1), (-0.5, 2)] {
+                let chaser = orbit(radius, 0., 1., rotation);
+                let target = orbit(radius, phase * rotation.signum(), 1., rotation);
+                let after = units::GameInstant(0);
+
+                let plan = phasing(&chaser, &target, revolutions, after, M).expect("valid phasing");
+                assert_eq!(plan.nodes().len(), 2);
+
+                let arrive = plan.nodes()[1].at();
+                let actual = plan.final_orbit().unwrap().to_state(arrive, M);
+                let expected = target.to_state(arrive, M);
+                // the arrival instant is rounded to a whole tick
+                let speed = circular_speed(radius);
+                assert!((actual.position().0 - expected.position().0).norm() <= speed);
+                assert!((actual.velocity().0 .0 - expected.velocity().0 .0).norm() < 1e-9);
+
+                let period = PI * 2. * (radius.powi(3) / M.0).sqrt();
+                let phasing_period = period * (1. - phase / (PI * 2. * f64::from(revolutions)));
+                let semimajor = (M.0 * (phasing_period / PI / 2.).powi(2)).cbrt();
+                let burn = (vis_viva(radius, semimajor) - speed).abs();
+                assert_relative(plan.nodes()[0].delta_v().0 .0.norm(), burn, 1e-9);
+                assert_relative(plan.total_delta_v().0, burn * 2., 1e-2);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_escape_trajectories() {
+        let circular = orbit(10000., 0., 1., Rotation::Counterclockwise);
+        let escape = orbit(10000., 0., 1.5, Rotation::Counterclockwise);
+        let after = units::GameInstant(0);
+        assert!(hohmann(&escape, &circular, after, M).is_none());
+        assert!(hohmann(&circular, &escape, after, M).is_none());
+        assert!(phasing(&circular, &escape, 1, after, M).is_none());
+        assert!(phasing(&circular, &circular, 0, after, M).is_none());
+    }
+}
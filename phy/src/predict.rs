@@ -0,0 +1,487 @@
+//! Predicts trajectories across nested star systems using patched conics.
+//!
+//! A trajectory is approximated by a sequence of conic segments,
+//! each of which is a Keplerian orbit in a single star system.
+//! A segment ends when the body crosses the field boundary of its parent star or of a child star,
+//! following the same rules as `soi::System`,
+//! and the next segment continues in the new star system.
+
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use getset::*;
+use specs::storage::MaskedStorage;
+use specs::{Entities, Entity, Join, Storage};
+
+use super::search::{self, SAMPLES_PER_SCALE};
+use super::soi::{to_child_frame, to_parent_frame};
+use super::{Body, Orbit, OrbitalState, Star};
+
+/// Number of samples taken while crossing the field radius of a child star at the highest speed.
+const SAMPLES_PER_FIELD: f64 = 4.;
+
+/// A conic segment of a predicted trajectory.
+#[derive(Debug, Clone, Getters, CopyGetters)]
+pub struct Segment {
+    /// The star entity in whose system this segment is expressed.
+    #[getset(get_copy = "pub")]
+    parent: Entity,
+    /// The orbit of the body during this segment.
+    #[getset(get = "pub")]
+    orbit: Orbit,
+    /// The instant at which this segment becomes valid.
+    #[getset(get_copy = "pub")]
+    start: units::GameInstant,
+    /// The instant at which this segment stops being valid.
+    #[getset(get_copy = "pub")]
+    end: units::GameInstant,
+    /// The reason this segment ends.
+    #[getset(get_copy = "pub")]
+    ending: Ending,
+}
+
+/// The reason a predicted segment ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ending {
+    /// The prediction horizon or the maximum number of transitions is reached.
+    Horizon,
+    /// The body moves into another star system, where the next segment starts.
+    Transition,
+    /// The body leaves the field of a root star.
+    Void,
+}
+
+/// A snapshot of the star hierarchy used to predict trajectories.
+///
+/// The snapshot does not borrow the storages,
+/// so it can be reused for multiple predictions within the same tick.
+#[derive(Debug)]
+pub struct Predictor {
+    stars: HashMap<Entity, StarInfo>,
+}
+
+/// The information of a star relevant to prediction.
+#[derive(Debug)]
+struct StarInfo {
+    strength: units::Mass,
+    field_radius: units::Length,
+    scale: f64,
+    /// The parent star and the motion of this star in it,
+    /// or `None` for a root star.
+    parent: Option<(Entity, Motion)>,
+    /// The child stars of this star.
+    children: Vec<Entity>,
+}
+
+/// The predicted motion of a body in its parent star system.
+#[derive(Debug)]
+pub(crate) enum Motion {
+    /// The body follows an orbit.
+    Orbit(Orbit),
+    /// The body moves at constant velocity from `state` at `since`.
+    ///
+    /// Accelerating bodies are assumed to stop accelerating.
+    Linear { state: OrbitalState, since: f64 },
+}
+
+/// A crossing of a field boundary.
+#[derive(Debug, Clone, Copy)]
+enum Crossing {
+    /// The body leaves the field of its parent star into the grandparent star.
+    Exit(Entity),
+    /// The body leaves the field of a root star.
+    Void,
+    /// The body enters the field of a child star.
+    Enter(Entity),
+}
+
+impl Predictor {
+    /// Takes a snapshot of all stars at time `t`.
+    pub fn new<DS, DB>(
+        entities: &Entities<'_>,
+        store_star: &Storage<'_, Star, DS>,
+        store_body: &Storage<'_, Body, DB>,
+        t: units::GameInstant,
+    ) -> Self
+    where
+        DS: Deref<Target = MaskedStorage<Star>>,
+        DB: Deref<Target = MaskedStorage<Body>>,
+    {
+        let mut stars: HashMap<Entity, StarInfo> = (entities, store_star)
+            .join()
+            .map(|(entity, star)| {
+                let parent = store_body.get(entity).and_then(|body| {
+                    let parent = body.parent()?;
+                    let m = store_star
+                        .get(parent)
+                        .expect("Body parent without a Star component")
+                        .strength();
+                    Some((parent, Motion::of(body, t, m)))
+                });
+                let info = StarInfo {
+                    strength: star.strength(),
+                    field_radius: star.field_radius(),
+                    scale: star.scale(),
+                    parent,
+                    children: vec![],
+                };
+                (entity, info)
+            })
+            .collect();
+
+        let edges: Vec<(Entity, Entity)> = stars
+            .iter()
+            .filter_map(|(&entity, info)| Some((info.parent.as_ref()?.0, entity)))
+            .collect();
+        for (parent, child) in edges {
+            if let Some(info) = stars.get_mut(&parent) {
+                info.children.push(child);
+            }
+        }
+
+        Self { stars }
+    }
+
+    /// Predicts the trajectory of a body following `orbit` around `parent` from `from`.
+    ///
+    /// The prediction stops at `horizon` or after `max_transitions` transitions between star
+    /// systems, whichever comes first, or when the body leaves the field of a root star.
+    /// The returned segments are contiguous and in chronological order.
+    ///
+    /// Transitions are reported at the first tick the body is beyond the boundary,
+    /// which is when `soi::System` would move the body.
+    /// Grazing crossings that are shorter than the sampling interval may be missed.
+    ///
+    /// Panics if `parent` is not a star in this snapshot.
+    pub fn predict(
+        &self,
+        orbit: &Orbit,
+        parent: Entity,
+        from: units::GameInstant,
+        horizon: units::GameInstant,
+        max_transitions: usize,
+    ) -> Vec<Segment> {
+        let mut segments = vec![];
+        let mut current = (parent, orbit.clone(), from);
+        loop {
+            let (parent, orbit, start) = current;
+            let next = if segments.len() < max_transitions {
+                self.next_crossing(parent, &orbit, start, horizon)
+            } else {
+                None
+            };
+
+            let (end, ending, next) = match next {
+                Some((end, Crossing::Void)) => (end, Ending::Void, None),
+                Some((end, crossing)) => (
+                    end,
+                    Ending::Transition,
+                    Some(self.transfer(parent, &orbit, end, crossing)),
+                ),
+                None => (horizon, Ending::Horizon, None),
+            };
+            segments.push(Segment {
+                parent,
+                orbit,
+                start,
+                end,
+                ending,
+            });
+            match next {
+                Some(next) => current = next,
+                None => return segments,
+            }
+        }
+    }
+
+    /// Retrieves a star in the snapshot.
+    fn star(&self, entity: Entity) -> &StarInfo {
+        self.stars
+            .get(&entity)
+            .expect("Body parent without a Star component")
+    }
+
+    /// Finds the first tick in `(start, horizon]` at which the body following `orbit` around
+    /// `parent` is beyond a field boundary.
+    fn next_crossing(
+        &self,
+        parent: Entity,
+        orbit: &Orbit,
+        start: units::GameInstant,
+        horizon: units::GameInstant,
+    ) -> Option<(units::GameInstant, Crossing)> {
+        let star = self.star(parent);
+        let speed = max_speed(orbit, star.strength);
+
+        let mut step = orbit.time_scale() / SAMPLES_PER_SCALE;
+        for &child in &star.children {
+            let child_star = self.star(child);
+            if let Some((_, motion)) = &child_star.parent {
+                let relative_speed = speed + motion.max_speed(star.strength);
+                let field = child_star.field_radius * child_star.scale;
+                step = step.min(field / relative_speed / SAMPLES_PER_FIELD);
+                if let Motion::Orbit(child_orbit) = motion {
+                    step = step.min(child_orbit.time_scale() / SAMPLES_PER_SCALE);
+                }
+            }
+        }
+
+        let crossing = |time: f64| self.crossing(star, orbit, time);
+        let mut previous = f64::from(start.0);
+        for time in search::sample_times(f64::from(start.0), f64::from(horizon.0), step).skip(1) {
+            if crossing(time).is_some() {
+                let (_, after) = search::bisect(|time| crossing(time).is_some(), previous, time);
+                let tick = units::GameInstant((after.ceil() as u32).min(horizon.0));
+                let kind = crossing(f64::from(tick.0))
+                    .or_else(|| crossing(after))
+                    .expect("bisection ends at a crossing");
+                return Some((tick, kind));
+            }
+            previous = time;
+        }
+        None
+    }
+
+    /// Checks whether the body following `orbit` around `star` is beyond a field boundary at
+    /// `time`.
+    fn crossing(&self, star: &StarInfo, orbit: &Orbit, time: f64) -> Option<Crossing> {
+        let position = orbit.position_at(time, 0.);
+        if position.0.norm() > star.field_radius {
+            return Some(match &star.parent {
+                Some((grandparent, _)) => Crossing::Exit(*grandparent),
+                None => Crossing::Void,
+            });
+        }
+
+        star.children.iter().copied().find_map(|child| {
+            let child_star = self.star(child);
+            let (_, motion) = child_star.parent.as_ref()?;
+            let distance = (position - motion.position_at(time)).0.norm();
+            if distance / child_star.scale < child_star.field_radius {
+                Some(Crossing::Enter(child))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Computes the first segment in the new star system after `crossing` at `t`.
+    fn transfer(
+        &self,
+        parent: Entity,
+        orbit: &Orbit,
+        t: units::GameInstant,
+        crossing: Crossing,
+    ) -> (Entity, Orbit, units::GameInstant) {
+        let star = self.star(parent);
+        let time = f64::from(t.0);
+        let state = orbit.to_state(t, star.strength);
+
+        let (target, state) = match crossing {
+            Crossing::Exit(grandparent) => {
+                let (_, motion) = star.parent.as_ref().expect("exit from a root star");
+                let m = self.star(grandparent).strength;
+                let star_state = motion.state_at(time, m);
+                (grandparent, to_parent_frame(state, star_state, star.scale))
+            }
+            Crossing::Enter(child) => {
+                let child_star = self.star(child);
+                let (_, motion) = child_star.parent.as_ref().expect("child star has a parent");
+                let star_state = motion.state_at(time, star.strength);
+                (child, to_child_frame(state, star_state, child_star.scale))
+            }
+            Crossing::Void => unreachable!("no transition into the void"),
+        };
+
+        let m = self.star(target).strength;
+        (target, Orbit::from_states(state, t, m), t)
+    }
+}
+
+impl Motion {
+    /// Predicts the motion of `body` from `t`.
+    ///
+    /// `m` is the strength of the parent star.
+    pub(crate) fn of(body: &Body, t: units::GameInstant, m: units::Mass) -> Self {
+        match body {
+            Body::Orbiting(body) => Self::Orbit(body.orbit().clone()),
+            _ => Self::Linear {
+                state: body.state(t, m),
+                since: f64::from(t.0),
+            },
+        }
+    }
+
+    /// Computes the position of the body at `time` ticks after `GameInstant::EPOCH`.
+    pub(crate) fn position_at(&self, time: f64) -> units::Position {
+        match self {
+            Self::Orbit(orbit) => orbit.position_at(time, 0.),
+            Self::Linear { state, since } => state.position() + state.velocity().0 * (time - since),
+        }
+    }
+
+    /// Computes the state of the body at `time` ticks after `GameInstant::EPOCH`.
+    ///
+    /// `m` is the strength of the parent star.
+    pub(crate) fn state_at(&self, time: f64, m: units::Mass) -> OrbitalState {
+        match self {
+            Self::Orbit(orbit) => {
+                OrbitalState::new(orbit.position_at(time, 0.), orbit.velocity_at(time, m, 0.))
+            }
+            Self::Linear { state, .. } => {
+                OrbitalState::new(self.position_at(time), state.velocity())
+            }
+        }
+    }
+
+    /// Computes an upper bound of the speed of the body.
+    ///
+    /// `m` is the strength of the parent star.
+    pub(crate) fn max_speed(&self, m: units::Mass) -> f64 {
+        match self {
+            Self::Orbit(orbit) => max_speed(orbit, m),
+            Self::Linear { state, .. } => state.velocity().0 .0.norm(),
+        }
+    }
+}
+
+/// Computes the speed at the periapsis, which is the highest speed along the orbit.
+fn max_speed(orbit: &Orbit, m: units::Mass) -> f64 {
+    (m.0 * (1. + orbit.eccentricity()) / orbit.periapsis_radius()).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+    use specs::{Builder, World, WorldExt};
+
+    use super::*;
+    use crate::{OrbitingBody, RootBody, Rotation};
+
+    const SUN: units::Mass = units::Mass(1e4);
+    const PLANET: units::Mass = units::Mass(10.);
+    const FIELD: units::Length = 500.;
+    const SCALE: f64 = 0.5;
+    const START: units::GameInstant = units::GameInstant(0);
+    const HORIZON: units::GameInstant = units::GameInstant(5000);
+
+    /// Creates a circular orbit around the sun at radius 10000, starting at bearing `bearing`.
+    fn circular(bearing: f64, rotation: Rotation) -> Orbit {
+        let radius = 10000.;
+        let speed = (SUN.0 / radius).sqrt() * rotation.signum();
+        let (sin, cos) = bearing.sin_cos();
+        let state = OrbitalState::new(
+            units::Position(Vector2::new(radius * cos, radius * sin)),
+            units::Velocity::of(units::Displace(Vector2::new(-speed * sin, speed * cos))),
+        );
+        Orbit::from_states(state, START, SUN)
+    }
+
+    /// Creates a sun and a planet orbiting it counterclockwise from bearing 0,
+    /// and takes a snapshot of them.
+    fn predictor() -> (Predictor, Entity, Entity, Orbit) {
+        let mut world = World::new();
+        world.register::<Body>();
+        world.register::<Star>();
+
+        let sun = world
+            .create_entity()
+            .with(Body::Root(RootBody))
+            .with(Star::new(1e6, SUN))
+            .build();
+        let orbit = circular(0., Rotation::Counterclockwise);
+        let mut planet = Star::new(FIELD, PLANET);
+        planet.set_scale(SCALE);
+        let planet = world
+            .create_entity()
+            .with(Body::Orbiting(OrbitingBody::new(orbit.clone(), sun)))
+            .with(planet)
+            .build();
+
+        let predictor = Predictor::new(
+            &world.entities(),
+            &world.read_storage::<Star>(),
+            &world.read_storage::<Body>(),
+            START,
+        );
+        (predictor, sun, planet, orbit)
+    }
+
+    fn assert_close(actual: OrbitalState, expected: OrbitalState) {
+        assert!(
+            (actual.position().0 - expected.position().0).norm() < 1e-6
+                && (actual.velocity().0 .0 - expected.velocity().0 .0).norm() < 1e-9,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn child_field_flyby() {
+        let (predictor, sun, planet, planet_orbit) = predictor();
+        // moving clockwise towards the planet
+        let ship = circular(0.3, Rotation::Clockwise);
+        let local = |t: units::GameInstant| {
+            to_child_frame(ship.to_state(t, SUN), planet_orbit.to_state(t, SUN), SCALE)
+        };
+
+        let segments = predictor.predict(&ship, sun, START, HORIZON, 5);
+        assert_eq!(segments.len(), 3, "{:?}", segments);
+
+        let enter = segments[0].end();
+        assert_eq!(segments[0].parent(), sun);
+        assert_eq!(segments[0].start(), START);
+        assert_eq!(segments[0].ending(), Ending::Transition);
+        // the first tick inside the field
+        assert!(local(enter).position().0.norm() < FIELD);
+        assert!(local(enter - units::GameDuration::UNIT).position().0.norm() >= FIELD);
+
+        let exit = segments[1].end();
+        assert_eq!(segments[1].parent(), planet);
+        assert_eq!(segments[1].start(), enter);
+        assert_eq!(segments[1].ending(), Ending::Transition);
+        let flyby = segments[1].orbit();
+        assert_close(flyby.to_state(enter, PLANET), local(enter));
+        // the first tick outside the field
+        assert!(flyby.approx_position(exit, 0.).0.norm() > FIELD);
+        assert!(
+            flyby
+                .approx_position(exit - units::GameDuration::UNIT, 0.)
+                .0
+                .norm()
+                <= FIELD
+        );
+
+        assert_eq!(segments[2].parent(), sun);
+        assert_eq!(segments[2].start(), exit);
+        assert_eq!(segments[2].end(), HORIZON);
+        assert_eq!(segments[2].ending(), Ending::Horizon);
+        assert_close(
+            segments[2].orbit().to_state(exit, SUN),
+            to_parent_frame(
+                flyby.to_state(exit, PLANET),
+                planet_orbit.to_state(exit, SUN),
+                SCALE,
+            ),
+        );
+
+        // the prediction stops at the transition limit
+        let limited = predictor.predict(&ship, sun, START, HORIZON, 1);
+        assert_eq!(limited.len(), 2);
+        assert_eq!(limited[0].end(), enter);
+        assert_eq!(limited[1].parent(), planet);
+        assert_eq!(limited[1].end(), HORIZON);
+        assert_eq!(limited[1].ending(), Ending::Horizon);
+
+        let none = predictor.predict(&ship, sun, START, HORIZON, 0);
+        assert_eq!(none.len(), 1);
+        assert_eq!(none[0].end(), HORIZON);
+        assert_eq!(none[0].ending(), Ending::Horizon);
+
+        // the prediction stops at the horizon before the transition
+        let short = predictor.predict(&ship, sun, START, enter - units::GameDuration::UNIT, 5);
+        assert_eq!(short.len(), 1);
+        assert_eq!(short[0].ending(), Ending::Horizon);
+    }
+}
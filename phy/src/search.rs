@@ -0,0 +1,67 @@
+//! Numerical searches over continuous time.
+//!
+//! Time is expressed as the number of ticks after `GameInstant::EPOCH`,
+//! which may be between two ticks.
+
+/// Number of samples taken per characteristic time of an orbit,
+/// i.e. the time taken to pass its periapsis.
+pub(crate) const SAMPLES_PER_SCALE: f64 = 8.;
+
+/// The maximum number of samples taken by `sample_times`.
+const MAX_SAMPLES: usize = 1 << 16;
+
+/// Number of iterations used by `minimize` and `bisect`.
+///
+/// Each iteration shrinks the interval by at least the golden ratio.
+const REFINE_ITERATIONS: u32 = 64;
+
+/// Returns sample times from `from` to `until` (both inclusive), spaced at most `step` apart.
+///
+/// If this requires too many samples, the spacing is increased to stay within `MAX_SAMPLES`.
+pub(crate) fn sample_times(from: f64, until: f64, step: f64) -> impl Iterator<Item = f64> {
+    let span = (until - from).max(0.);
+    let count = ((span / step).ceil() as usize).clamp(1, MAX_SAMPLES);
+    let step = span / count as f64;
+    (0..=count).map(move |i| {
+        if i == count {
+            until
+        } else {
+            from + step * i as f64
+        }
+    })
+}
+
+/// Finds the minimum of a function that is unimodal in `[low, high]` by golden-section search.
+pub(crate) fn minimize(f: impl Fn(f64) -> f64, mut low: f64, mut high: f64) -> f64 {
+    let ratio = (5f64.sqrt() - 1.) / 2.;
+    for _ in 0..REFINE_ITERATIONS {
+        let left = high - (high - low) * ratio;
+        let right = low + (high - low) * ratio;
+        if f(left) < f(right) {
+            high = right;
+        } else {
+            low = left;
+        }
+    }
+    (low + high) / 2.
+}
+
+/// Finds the time in `[low, high]` at which `reached` changes from false to true,
+/// assuming `reached(low)` is false and `reached(high)` is true.
+///
+/// Returns the final bracket `(before, after)`,
+/// where `reached(before)` is false and `reached(after)` is true.
+pub(crate) fn bisect(reached: impl Fn(f64) -> bool, mut low: f64, mut high: f64) -> (f64, f64) {
+    for _ in 0..REFINE_ITERATIONS {
+        let mid = (low + high) / 2.;
+        if mid <= low || mid >= high {
+            break;
+        }
+        if reached(mid) {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    (low, high)
+}
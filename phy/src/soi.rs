@@ -0,0 +1,357 @@
+//! Moves bodies between nested star systems
+//! when they cross the boundary of a gravitational field.
+//!
+//! A body belongs to the star system of the innermost field containing it.
+//! Each tick, a body outside the field of its parent star is moved to the grandparent star,
+//! and a body inside the field of a child star of its parent is moved into that child star.
+//! Bodies leaving the field of a root star trigger `collision::Event::Void` instead,
+//! once per exit.
+
+use std::collections::{HashMap, HashSet};
+
+use shrev::EventChannel;
+use specs::{Entities, Entity, Join, ReadExpect, ReadStorage, Write, WriteStorage};
+
+use super::collision::{BoundingBox, Event};
+use super::{Body, BodyIndex, OrbitalState, Star};
+
+/// The system moving bodies between star systems.
+///
+/// Only orbiting and accelerating bodies are moved.
+/// Stars themselves stay in their star systems.
+///
+/// A body outside the field of a root star stays in that star system.
+/// `Event::Void` is only emitted on the tick it leaves the field,
+/// and again only if it re-enters the field and leaves it once more.
+#[derive(Default)]
+pub struct System {
+    /// The bodies outside the field of their root star in the previous tick.
+    void: HashSet<Entity>,
+}
+
+/// A pending move of a body to another star system.
+struct Transition {
+    subject: Entity,
+    from: Entity,
+    to: Entity,
+    /// The state of `subject` in the star system of `to`.
+    state: OrbitalState,
+}
+
+impl<'a> specs::System<'a> for System {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, units::GameInstant>,
+        WriteStorage<'a, Body>,
+        WriteStorage<'a, Star>,
+        ReadStorage<'a, BoundingBox>,
+        Write<'a, EventChannel<Event>>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, t, mut store_body, mut store_star, store_bb, mut events): Self::SystemData,
+    ) {
+        let t = *t;
+
+        // the child stars of each star, with their states in the parent star system
+        let mut children: HashMap<Entity, Vec<(Entity, OrbitalState)>> = HashMap::new();
+        for (entity, _, body) in (&entities, &store_star, &store_body).join() {
+            if let Some(parent) = body.parent() {
+                let state = body.state(t, strength(&store_star, parent));
+                children.entry(parent).or_default().push((entity, state));
+            }
+        }
+
+        let mut transitions = vec![];
+        let mut void = HashSet::new();
+        for (entity, body, ()) in (&entities, &store_body, !&store_star).join() {
+            let parent = match body {
+                Body::Orbiting(body) => body.parent(),
+                Body::Accelerating(body) => body.parent(),
+                Body::Root(_) | Body::Standing(_) => continue,
+            };
+            let star = store_star
+                .get(parent)
+                .expect("Body parent without a Star component");
+            let state = body.state(t, star.strength());
+
+            if state.position().0.norm() > star.field_radius() {
+                let grandparent = store_body
+                    .get(parent)
+                    .and_then(|star_body| Some((star_body, star_body.parent()?)));
+                match grandparent {
+                    Some((star_body, grandparent)) => {
+                        let star_state = star_body.state(t, strength(&store_star, grandparent));
+                        transitions.push(Transition {
+                            subject: entity,
+                            from: parent,
+                            to: grandparent,
+                            state: to_parent_frame(state, star_state, star.scale()),
+                        });
+                    }
+                    None => {
+                        if !self.void.contains(&entity) {
+                            events.single_write(Event::Void {
+                                subject: entity,
+                                position: state.position(),
+                                velocity: state.velocity(),
+                            });
+                        }
+                        let _ = void.insert(entity);
+                    }
+                }
+                continue;
+            }
+
+            let siblings = children.get(&parent).map_or(&[][..], |vec| &vec[..]);
+            for &(child, star_state) in siblings {
+                let child_star = store_star
+                    .get(child)
+                    .expect("Star child was joined with Star");
+                let local = to_child_frame(state, star_state, child_star.scale());
+                if local.position().0.norm() < child_star.field_radius() {
+                    transitions.push(Transition {
+                        subject: entity,
+                        from: parent,
+                        to: child,
+                        state: local,
+                    });
+                    break;
+                }
+            }
+        }
+
+        self.void = void;
+
+        if transitions.is_empty() {
+            return;
+        }
+        let extents = BodyIndex::extents(&store_bb);
+        for transition in transitions {
+            let m = strength(&store_star, transition.to);
+            store_body
+                .get_mut(transition.subject)
+                .expect("Transition subject was joined with Body")
+                .reparent(transition.state, t, transition.to, m);
+            store_star
+                .get_mut(transition.from)
+                .expect("Body parent without a Star component")
+                .index_mut()
+                .remove(transition.subject, t);
+            store_star
+                .get_mut(transition.to)
+                .expect("Body parent without a Star component")
+                .index_mut()
+                .insert(transition.subject, &store_body, &extents, t);
+        }
+    }
+}
+
+/// Retrieves the strength of the star `entity`.
+fn strength(store_star: &WriteStorage<'_, Star>, entity: Entity) -> units::Mass {
+    store_star
+        .get(entity)
+        .expect("Body parent without a Star component")
+        .strength()
+}
+
+/// Re-expresses `state` in the parent star system of a star,
+/// where `star_state` is the state of the star in its parent star system.
+///
+/// `scale` is the `Star::scale` of the star.
+pub(crate) fn to_parent_frame(
+    state: OrbitalState,
+    star_state: OrbitalState,
+    scale: f64,
+) -> OrbitalState {
+    OrbitalState::new(
+        star_state.position() + units::Displace(state.position().0 * scale),
+        star_state.velocity() + state.velocity() * scale,
+    )
+}
+
+/// Re-expresses `state` in the star system of a child star,
+/// where `star_state` is the state of the child star in the same star system as `state`.
+///
+/// `scale` is the `Star::scale` of the child star.
+pub(crate) fn to_child_frame(
+    state: OrbitalState,
+    star_state: OrbitalState,
+    scale: f64,
+) -> OrbitalState {
+    OrbitalState::new(
+        units::Position::origin() + (state.position() - star_state.position()) / scale,
+        (state.velocity() - star_state.velocity()) / scale,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+    use specs::{Builder, RunNow, World, WorldExt};
+
+    use super::*;
+    use crate::{AccelBody, Orbit, OrbitingBody, RootBody};
+
+    const SUN: units::Mass = units::Mass(1000.);
+    const PLANET: units::Mass = units::Mass(1.);
+    const SCALE: f64 = 0.5;
+
+    fn state(px: f64, py: f64, vx: f64, vy: f64) -> OrbitalState {
+        OrbitalState::new(
+            units::Position(Vector2::new(px, py)),
+            units::Velocity::of(units::Displace(Vector2::new(vx, vy))),
+        )
+    }
+
+    fn assert_close(actual: OrbitalState, expected: OrbitalState) {
+        assert!(
+            (actual.position().0 - expected.position().0).norm() < 1e-9
+                && (actual.velocity().0 .0 - expected.velocity().0 .0).norm() < 1e-9,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    /// Creates a sun with a field radius of 1000 and a planet orbiting it at radius 100,
+    /// which has a field radius of 10 and a scale of 0.5.
+    fn world() -> (World, Entity, Entity) {
+        let mut world = World::new();
+        world.register::<Body>();
+        world.register::<Star>();
+        world.register::<BoundingBox>();
+        world.insert(EventChannel::<Event>::new());
+        world.insert(units::GameInstant(0));
+
+        let sun = world
+            .create_entity()
+            .with(Body::Root(RootBody))
+            .with(Star::new(1000., SUN))
+            .build();
+        let orbit = Orbit::from_states(
+            state(100., 0., 0., (SUN.0 / 100.).sqrt()),
+            units::GameInstant(0),
+            SUN,
+        );
+        let mut planet = Star::new(10., PLANET);
+        planet.set_scale(SCALE);
+        let planet = world
+            .create_entity()
+            .with(Body::Orbiting(OrbitingBody::new(orbit, sun)))
+            .with(planet)
+            .build();
+        (world, sun, planet)
+    }
+
+    /// Creates an accelerating body with the state `state` in the star system of `parent`.
+    fn spawn(world: &mut World, state: OrbitalState, parent: Entity) -> Entity {
+        let entity = world
+            .create_entity()
+            .with(Body::Accelerating(AccelBody::from_state(state, parent)))
+            .build();
+        let t = *world.read_resource::<units::GameInstant>();
+        let store_body = world.read_storage::<Body>();
+        let store_bb = world.read_storage::<BoundingBox>();
+        world
+            .write_storage::<Star>()
+            .get_mut(parent)
+            .unwrap()
+            .index_mut()
+            .insert(entity, &store_body, &BodyIndex::extents(&store_bb), t);
+        entity
+    }
+
+    fn contains(world: &World, star: Entity, entity: Entity) -> bool {
+        world
+            .read_storage::<Star>()
+            .get(star)
+            .unwrap()
+            .index()
+            .all()
+            .any(|child| child == entity)
+    }
+
+    #[test]
+    fn child_field_round_trip() {
+        let (mut world, sun, planet) = world();
+        let mut system = System::default();
+        let t = units::GameInstant(0);
+
+        let planet_state = world
+            .read_storage::<Body>()
+            .get(planet)
+            .unwrap()
+            .state(t, SUN);
+        // 4 units away from the planet, which is 8 units in the planet system
+        let outside = to_parent_frame(state(8., 0., 0.1, 0.2), planet_state, SCALE);
+        assert_close(
+            outside,
+            OrbitalState::new(
+                planet_state.position() + units::Displace(Vector2::new(4., 0.)),
+                planet_state.velocity()
+                    + units::Velocity::of(units::Displace(Vector2::new(0.05, 0.1))),
+            ),
+        );
+        let ship = spawn(&mut world, outside, sun);
+
+        system.run_now(&world);
+        let body = world.read_storage::<Body>();
+        let ship_body = body.get(ship).unwrap();
+        assert_eq!(ship_body.parent(), Some(planet));
+        assert_close(ship_body.state(t, PLANET), state(8., 0., 0.1, 0.2));
+        drop(body);
+        assert!(contains(&world, planet, ship));
+        assert!(!contains(&world, sun, ship));
+
+        // move the ship beyond the planet field
+        let local = state(12., 0., 0.1, 0.2);
+        *world.write_storage::<Body>().get_mut(ship).unwrap() =
+            Body::Accelerating(AccelBody::from_state(local, planet));
+        system.run_now(&world);
+        let body = world.read_storage::<Body>();
+        let ship_body = body.get(ship).unwrap();
+        assert_eq!(ship_body.parent(), Some(sun));
+        let global = ship_body.state(t, SUN);
+        assert_close(global, to_parent_frame(local, planet_state, SCALE));
+        assert_close(to_child_frame(global, planet_state, SCALE), local);
+        drop(body);
+        assert!(contains(&world, sun, ship));
+        assert!(!contains(&world, planet, ship));
+    }
+
+    #[test]
+    fn void_once_per_exit() {
+        let (mut world, sun, _) = world();
+        let mut system = System::default();
+        let mut reader = world
+            .write_resource::<EventChannel<Event>>()
+            .register_reader();
+        let ship = spawn(&mut world, state(-500., 0., 0., 1.), sun);
+
+        let mut voids = |world: &World, system: &mut System| {
+            system.run_now(world);
+            world
+                .read_resource::<EventChannel<Event>>()
+                .read(&mut reader)
+                .filter(|event| matches!(event, Event::Void { subject, .. } if *subject == ship))
+                .count()
+        };
+        let place = |world: &World, x: f64| {
+            *world.write_storage::<Body>().get_mut(ship).unwrap() =
+                Body::Accelerating(AccelBody::from_state(state(x, 0., 0., 1.), sun));
+        };
+
+        assert_eq!(voids(&world, &mut system), 0);
+        place(&world, -1100.);
+        assert_eq!(voids(&world, &mut system), 1);
+        assert_eq!(voids(&world, &mut system), 0);
+        place(&world, -1200.);
+        assert_eq!(voids(&world, &mut system), 0);
+        place(&world, -900.);
+        assert_eq!(voids(&world, &mut system), 0);
+        place(&world, -1100.);
+        assert_eq!(voids(&world, &mut system), 1);
+    }
+}
@@ -164,6 +164,7 @@ pub fn save_star(
     #[derive(Serialize)]
     struct StarSer<I: Iterator<Item = BodySer> + Clone> {
         field_radius: units::Length,
+        scale: f64,
         strength: units::Mass,
         #[serde(with = "serde_iter::seq")]
         children: I,
@@ -199,6 +200,7 @@ pub fn save_star(
         &mut file,
         &StarSer {
             field_radius: star.field_radius(),
+            scale: star.scale(),
             strength: star.strength(),
             children: serde_iter::CloneOnce::from(children),
         },
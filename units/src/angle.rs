@@ -52,7 +52,7 @@ impl Theta {
 
     /// Computes the tangent of this angle.
     pub fn tan(self) -> f64 {
-        self.0.cos()
+        self.0.tan()
     }
 }
 
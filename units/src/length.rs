@@ -69,6 +69,9 @@ impl std::ops::Sub<Position> for Position {
 /// A velocity in displacement per `GameDuration` tick.
 pub type Velocity = super::Rate<Displace>;
 
+/// A scalar speed in length per `GameDuration` tick.
+pub type Speed = super::Rate<Length>;
+
 /// An acceleration in velocity per `GameDuration` tick.
 pub type Accel = super::Rate<Velocity>;
 